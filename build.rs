@@ -24,6 +24,7 @@ fn main() {
             sameboy.file(file.path());
         }
     }
+    sameboy.file("src/sameboy_internals.c");
     sameboy.compile("sameboy");
 
     let bindings = bindgen::builder()
@@ -0,0 +1,200 @@
+//! Audio output.
+//!
+//! SameBoy produces samples from the emulation thread, one at a time, at whatever rate we ask it to.
+//! Those are pushed into a lock-free ring buffer, which cpal's audio thread drains at its own pace.
+//! The two clocks never quite agree, so the sink resamples slightly to keep the buffer around the target latency.
+
+use std::fmt::Display;
+
+use cpal::{
+    FromSample, SizedSample,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use ringbuf::{
+    HeapCons, HeapProd, HeapRb,
+    traits::{Consumer, Observer, Producer, Split},
+};
+
+/// A stereo sample, normalised to `-1.0..=1.0`.
+pub type StereoSample = [f32; 2];
+
+/// How much audio the ring buffer can hold, in seconds.
+const BUFFER_LENGTH: f32 = 0.1;
+/// How much audio must be buffered (in seconds) before playback starts or resumes after an underrun.
+const TARGET_LATENCY: f32 = 0.04;
+/// Per-sample factor applied to the last output sample during an underrun, so that it fades to silence
+/// instead of dropping there abruptly (which would click).
+const UNDERRUN_DECAY: f32 = 0.998;
+/// How much the sink may speed up or slow down the audio to compensate for drift; this is well below what
+/// can be heard as a change in pitch.
+const MAX_RATE_ADJUSTMENT: f32 = 0.005;
+
+/// Keeps the output stream alive; audio stops once this is dropped.
+///
+/// This must stay on the thread that created it, as streams are not `Send` on all platforms.
+pub struct AudioOutput {
+    _stream: cpal::Stream,
+}
+
+/// The emulator's end of the audio pipeline.
+pub struct AudioSink {
+    producer: HeapProd<StereoSample>,
+    sample_rate: u32,
+    /// How many samples should ideally be buffered.
+    target_len: usize,
+    /// The sample pushed before the current one, to interpolate from.
+    previous: StereoSample,
+    /// Where the next output sample lies between `previous` and the sample being pushed.
+    phase: f32,
+}
+
+impl AudioSink {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Queues a sample for playback.
+    pub fn push(&mut self, sample: StereoSample) {
+        // Fewer samples are output while the buffer is fuller than the target, and more while it is emptier.
+        let fill = self.producer.occupied_len() as f32 / self.target_len as f32;
+        let step = 1.0
+            + ((fill - 1.0) * MAX_RATE_ADJUSTMENT).clamp(-MAX_RATE_ADJUSTMENT, MAX_RATE_ADJUSTMENT);
+        while self.phase < 1.0 {
+            let interpolated =
+                [0, 1].map(|i| self.previous[i] + (sample[i] - self.previous[i]) * self.phase);
+            // The buffer can only be full if the emulator ran way ahead, e.g. right after resuming;
+            // the rate control will catch up shortly.
+            let _ = self.producer.try_push(interpolated);
+            self.phase += step;
+        }
+        self.phase -= 1.0;
+        self.previous = sample;
+    }
+}
+
+#[derive(Debug)]
+pub enum AudioError {
+    NoDevice,
+    DefaultConfig(cpal::DefaultStreamConfigError),
+    UnsupportedFormat(cpal::SampleFormat),
+    BuildStream(cpal::BuildStreamError),
+    PlayStream(cpal::PlayStreamError),
+}
+impl Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoDevice => write!(f, "no output device available"),
+            Self::DefaultConfig(err) => {
+                write!(f, "unable to query the device's configuration: {err}")
+            }
+            Self::UnsupportedFormat(format) => write!(f, "unsupported sample format {format}"),
+            Self::BuildStream(err) => write!(f, "unable to create output stream: {err}"),
+            Self::PlayStream(err) => write!(f, "unable to start output stream: {err}"),
+        }
+    }
+}
+impl std::error::Error for AudioError {}
+
+/// Opens the default output device, using its native configuration.
+pub fn open() -> Result<(AudioOutput, AudioSink), AudioError> {
+    let host = cpal::default_host();
+    let device = host.default_output_device().ok_or(AudioError::NoDevice)?;
+    let supported_config = device
+        .default_output_config()
+        .map_err(AudioError::DefaultConfig)?;
+    let config = supported_config.config();
+    let sample_rate = config.sample_rate.0;
+
+    let ring_buffer = HeapRb::new((sample_rate as f32 * BUFFER_LENGTH) as usize);
+    let (producer, consumer) = ring_buffer.split();
+    let target_len = (sample_rate as f32 * TARGET_LATENCY) as usize;
+    let playback = Playback {
+        consumer,
+        last: [0.0; 2],
+        primed: false,
+        target_len,
+    };
+
+    let stream = match supported_config.sample_format() {
+        cpal::SampleFormat::I8 => build_stream::<i8>(&device, &config, playback),
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, playback),
+        cpal::SampleFormat::I32 => build_stream::<i32>(&device, &config, playback),
+        cpal::SampleFormat::I64 => build_stream::<i64>(&device, &config, playback),
+        cpal::SampleFormat::U8 => build_stream::<u8>(&device, &config, playback),
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, playback),
+        cpal::SampleFormat::U32 => build_stream::<u32>(&device, &config, playback),
+        cpal::SampleFormat::U64 => build_stream::<u64>(&device, &config, playback),
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, playback),
+        cpal::SampleFormat::F64 => build_stream::<f64>(&device, &config, playback),
+        format => Err(AudioError::UnsupportedFormat(format)),
+    }?;
+    stream.play().map_err(AudioError::PlayStream)?;
+
+    Ok((
+        AudioOutput { _stream: stream },
+        AudioSink {
+            producer,
+            sample_rate,
+            target_len,
+            previous: [0.0; 2],
+            phase: 0.0,
+        },
+    ))
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut playback: Playback,
+) -> Result<cpal::Stream, AudioError> {
+    let nb_channels = config.channels as usize;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| playback.fill(data, nb_channels),
+            |err| eprintln!("Audio stream error: {err}"),
+            None,
+        )
+        .map_err(AudioError::BuildStream)
+}
+
+/// The audio thread's end of the audio pipeline.
+struct Playback {
+    consumer: HeapCons<StereoSample>,
+    /// The last sample that was output, used to smooth over underruns.
+    last: StereoSample,
+    /// Whether enough samples have been buffered to play without immediately underrunning.
+    primed: bool,
+    /// How many samples must be buffered for playback to be primed.
+    target_len: usize,
+}
+
+impl Playback {
+    fn fill<T: SizedSample + FromSample<f32>>(&mut self, data: &mut [T], nb_channels: usize) {
+        if !self.primed && self.consumer.occupied_len() >= self.target_len {
+            self.primed = true;
+        }
+
+        for frame in data.chunks_mut(nb_channels) {
+            let sample = match self.primed.then(|| self.consumer.try_pop()).flatten() {
+                Some(sample) => sample,
+                None => {
+                    // Underrun: wait for the buffer to fill back up, and fade out meanwhile.
+                    self.primed = false;
+                    self.last.map(|amplitude| amplitude * UNDERRUN_DECAY)
+                }
+            };
+            self.last = sample;
+
+            match frame {
+                [mono] => *mono = T::from_sample((sample[0] + sample[1]) / 2.0),
+                [left, right, rest @ ..] => {
+                    *left = T::from_sample(sample[0]);
+                    *right = T::from_sample(sample[1]);
+                    rest.fill(T::EQUILIBRIUM);
+                }
+                [] => unreachable!(),
+            }
+        }
+    }
+}
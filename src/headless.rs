@@ -0,0 +1,257 @@
+//! Running a ROM without any UI, for automated testing.
+//!
+//! ```text
+//! aythya --headless rom.gb --frames 600 --screenshot out.png
+//! aythya --headless --test cpu_instrs.gb
+//! ```
+
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    path::PathBuf,
+    sync::{Arc, mpsc},
+};
+
+use slint::{Rgba8Pixel, SharedPixelBuffer};
+
+use crate::{
+    sameboy::{Frontend, GB_log_attributes_t, MODELS, ModelChoice, SameBoy},
+    screenshot,
+    testrom::TestMonitor,
+};
+
+const USAGE: &str = "Usage: aythya --headless <ROM> (--frames <N> | --cycles <N> | --test) [--model <MODEL>] [--screenshot <PNG>] [--save]
+
+    --frames <N>        Run for this many frames.
+    --cycles <N>        Run for this many clock cycles (at 4 MiHz).
+    --test              Stop as soon as a test ROM reports its result, and print it.
+                        --frames and --cycles then set a timeout (default: 2 minutes' worth of frames).
+    --model <MODEL>     The model to emulate (default: auto).
+    --screenshot <PNG>  Write the screen's contents to this file once done.
+    --save              Load the ROM's battery save, and write it back once done.
+                        By default, cartridge RAM starts out blank, and nothing is written.
+
+Exit codes: 0 on success, 1 on error or test failure, 2 on usage error, 3 on test timeout.";
+
+/// Process exit codes.
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_TIMEOUT: i32 = 3;
+
+/// How long test mode waits for a result by default.
+const DEFAULT_TEST_TIMEOUT: Duration = Duration::Frames(2 * 60 * 60);
+/// In test mode, results are checked at least this often, in cycles (that's a frame).
+const TEST_CHECK_INTERVAL: u64 = 70224;
+
+/// How long to run the ROM for.
+#[derive(Debug, Clone, Copy)]
+enum Duration {
+    Frames(u64),
+    Cycles(u64),
+}
+
+#[derive(Debug)]
+struct Options {
+    rom: PathBuf,
+    duration: Duration,
+    model: ModelChoice,
+    screenshot: Option<PathBuf>,
+    test: bool,
+    save: bool,
+}
+
+#[derive(Debug)]
+enum UsageError {
+    MissingRom,
+    MissingDuration,
+    ExtraArgument(OsString),
+    MissingValue(&'static str),
+    BadNumber(&'static str, OsString),
+    BadModel(OsString),
+}
+impl Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingRom => write!(f, "No ROM specified"),
+            Self::MissingDuration => {
+                write!(f, "One of --frames, --cycles, or --test must be specified")
+            }
+            Self::ExtraArgument(arg) => write!(f, "Unexpected argument \"{}\"", arg.display()),
+            Self::MissingValue(option) => write!(f, "{option} requires a value"),
+            Self::BadNumber(option, value) => {
+                write!(f, "{option} expects a number, not \"{}\"", value.display())
+            }
+            Self::BadModel(name) => {
+                write!(
+                    f,
+                    "Unknown model \"{}\"; expected one of: auto",
+                    name.display()
+                )?;
+                for (_, name, _) in MODELS {
+                    write!(f, ", {name}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments following `--headless`.
+    fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Self, UsageError> {
+        let mut args = args.into_iter();
+        let mut rom = None;
+        let mut duration = None;
+        let mut model = ModelChoice::Auto;
+        let mut screenshot = None;
+        let mut test = false;
+        let mut save = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = |option| args.next().ok_or(UsageError::MissingValue(option));
+            let number = |option, value: OsString| {
+                value
+                    .to_str()
+                    .and_then(|string| string.parse().ok())
+                    .ok_or(UsageError::BadNumber(option, value))
+            };
+
+            match arg.to_str() {
+                Some("--frames") => {
+                    duration = Some(Duration::Frames(number("--frames", value("--frames")?)?));
+                }
+                Some("--cycles") => {
+                    duration = Some(Duration::Cycles(number("--cycles", value("--cycles")?)?));
+                }
+                Some("--model") => {
+                    let name = value("--model")?;
+                    model = name
+                        .to_str()
+                        .and_then(ModelChoice::from_name)
+                        .ok_or(UsageError::BadModel(name))?;
+                }
+                Some("--screenshot") => screenshot = Some(value("--screenshot")?.into()),
+                Some("--test") => test = true,
+                Some("--save") => save = true,
+                _ if rom.is_none() => rom = Some(arg.into()),
+                _ => return Err(UsageError::ExtraArgument(arg)),
+            }
+        }
+
+        Ok(Self {
+            rom: rom.ok_or(UsageError::MissingRom)?,
+            duration: match duration {
+                Some(duration) => duration,
+                None if test => DEFAULT_TEST_TIMEOUT,
+                None => return Err(UsageError::MissingDuration),
+            },
+            model,
+            screenshot,
+            test,
+            save,
+        })
+    }
+}
+
+/// Prints SameBoy's output to stderr, and ignores the screen (it's only read once done).
+struct HeadlessFrontend;
+
+impl Frontend for HeadlessFrontend {
+    fn update_screen(&mut self, _screen: SharedPixelBuffer<Rgba8Pixel>) {}
+
+    fn log(&mut self, text: &str, _attributes: GB_log_attributes_t) {
+        eprint!("{text}");
+    }
+
+    fn recording_aborted(&mut self, err: std::io::Error) {
+        eprintln!("Recording stopped: {err}");
+    }
+}
+
+/// Runs headless mode with the arguments following `--headless`, and returns the process' exit code.
+pub fn run(args: impl IntoIterator<Item = OsString>) -> i32 {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return EXIT_USAGE;
+        }
+    };
+
+    // Nobody can send debugger commands, so if the debugger breaks, SameBoy will be told to carry on.
+    let (_, debugger_receiver) = mpsc::channel();
+    let mut sameboy = SameBoy::new(
+        Box::new(HeadlessFrontend),
+        debugger_receiver,
+        None,
+        Arc::default(),
+        Arc::default(),
+    );
+    sameboy.set_model(options.model);
+    // Runs should be reproducible, and not overwrite the player's saves.
+    sameboy.set_battery_saves(options.save);
+    if let Err(err) = sameboy.load_rom(&options.rom) {
+        eprintln!("Unable to load ROM: {err}");
+        return EXIT_FAILURE;
+    }
+    sameboy.disable_timekeeping();
+    if options.test {
+        sameboy.monitor_tests();
+    }
+
+    let mut remaining = options.duration;
+    let outcome = loop {
+        match &mut remaining {
+            Duration::Frames(0) | Duration::Cycles(0) => break None,
+            Duration::Frames(frames) => {
+                sameboy.run_frame();
+                *frames -= 1;
+            }
+            Duration::Cycles(cycles) => {
+                let ran = sameboy.run_cycles((*cycles).min(TEST_CHECK_INTERVAL));
+                *cycles = cycles.saturating_sub(ran);
+            }
+        }
+        if let Some(outcome) = sameboy.test_monitor().and_then(TestMonitor::outcome) {
+            break Some(outcome.clone());
+        }
+    };
+    sameboy.save_battery();
+
+    let mut exit_code = EXIT_SUCCESS;
+    if options.test {
+        match outcome {
+            Some(outcome) => {
+                println!(
+                    "{} ({})",
+                    if outcome.passed { "PASS" } else { "FAIL" },
+                    outcome.convention,
+                );
+                if !outcome.details.is_empty() {
+                    println!("{}", outcome.details);
+                }
+                if !outcome.passed {
+                    exit_code = EXIT_FAILURE;
+                }
+            }
+            None => {
+                println!("TIMEOUT (no result reported)");
+                if let Some(monitor) = sameboy.test_monitor()
+                    && !monitor.serial_output().trim().is_empty()
+                {
+                    println!("Serial output:\n{}", monitor.serial_output().trim());
+                }
+                exit_code = EXIT_TIMEOUT;
+            }
+        }
+    }
+
+    if let Some(path) = options.screenshot
+        && let Err(err) = screenshot::write_png(&path, &sameboy.screen_pixels())
+    {
+        eprintln!("Unable to write screenshot to {}: {err}", path.display());
+        return EXIT_FAILURE;
+    }
+    exit_code
+}
@@ -0,0 +1,308 @@
+//! Joypad input.
+//!
+//! Key events are received by the UI thread, but must be applied to the emulator from the emulation thread,
+//! which holds on to the emulator for as long as it's running; so the joypad state is shared through an atomic.
+
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use slint::{SharedString, platform::Key};
+
+/// The Game Boy's buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+impl Button {
+    pub const ALL: [Self; 8] = [
+        Self::Right,
+        Self::Left,
+        Self::Up,
+        Self::Down,
+        Self::A,
+        Self::B,
+        Self::Select,
+        Self::Start,
+    ];
+
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// A stable identifier, used for persisting bindings.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Right => "right",
+            Self::Left => "left",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::A => "a",
+            Self::B => "b",
+            Self::Select => "select",
+            Self::Start => "start",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|button| button.name() == name)
+    }
+
+    /// A human-readable name.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Right => "Right",
+            Self::Left => "Left",
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::A => "A",
+            Self::B => "B",
+            Self::Select => "Select",
+            Self::Start => "Start",
+        }
+    }
+}
+
+/// Which buttons are currently held.
+#[derive(Debug, Default)]
+pub struct Joypad(AtomicU8);
+impl Joypad {
+    pub fn set(&self, button: Button, pressed: bool) {
+        if pressed {
+            self.0.fetch_or(button.mask(), Ordering::Relaxed);
+        } else {
+            self.0.fetch_and(!button.mask(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns a snapshot of the joypad, which can be queried with [`JoypadState::is_pressed`].
+    pub fn state(&self) -> JoypadState {
+        JoypadState(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JoypadState(u8);
+impl JoypadState {
+    pub fn is_pressed(self, button: Button) -> bool {
+        self.0 & button.mask() != 0
+    }
+}
+
+/// Which of the hotkeys that act for as long as they are held are currently held.
+#[derive(Debug, Default)]
+pub struct HeldHotkeys {
+    rewind: AtomicBool,
+    fast_forward: AtomicBool,
+}
+impl HeldHotkeys {
+    pub fn set_rewinding(&self, rewinding: bool) {
+        self.rewind.store(rewinding, Ordering::Relaxed);
+    }
+
+    pub fn is_rewinding(&self) -> bool {
+        self.rewind.load(Ordering::Relaxed)
+    }
+
+    pub fn set_fast_forwarding(&self, fast_forwarding: bool) {
+        self.fast_forward.store(fast_forwarding, Ordering::Relaxed);
+    }
+
+    pub fn is_fast_forwarding(&self) -> bool {
+        self.fast_forward.load(Ordering::Relaxed)
+    }
+}
+
+/// Associates keys (as reported by Slint's key events) to buttons.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(SharedString, Button)>,
+}
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Key::RightArrow.into(), Button::Right),
+                (Key::LeftArrow.into(), Button::Left),
+                (Key::UpArrow.into(), Button::Up),
+                (Key::DownArrow.into(), Button::Down),
+                ("x".into(), Button::A),
+                ("z".into(), Button::B),
+                (Key::Backspace.into(), Button::Select),
+                (Key::Return.into(), Button::Start),
+            ],
+        }
+    }
+}
+impl Keymap {
+    pub fn empty() -> Self {
+        Self { bindings: vec![] }
+    }
+
+    /// Binds a key to a button; the key stays bound to any other buttons it was already bound to.
+    pub fn bind(&mut self, key: &str, button: Button) {
+        let key = normalise_key(key);
+        if !self.bindings.contains(&(key.clone(), button)) {
+            self.bindings.push((key, button));
+        }
+    }
+
+    /// Unbinds all keys from a button.
+    pub fn clear(&mut self, button: Button) {
+        self.bindings
+            .retain(|(_, bound_button)| *bound_button != button);
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (&SharedString, Button)> {
+        self.bindings.iter().map(|(key, button)| (key, *button))
+    }
+
+    /// Returns the keys bound to a button.
+    pub fn keys(&self, button: Button) -> impl Iterator<Item = &SharedString> {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound_button)| *bound_button == button)
+            .map(|(key, _)| key)
+    }
+
+    /// Returns the buttons bound to a key.
+    pub fn buttons(&self, key: &str) -> impl Iterator<Item = Button> {
+        // Shift turns letters uppercase, but we don't want it to affect the binding.
+        let key = normalise_key(key);
+        self.bindings
+            .iter()
+            .filter(move |(bound_key, _)| *bound_key == key)
+            .map(|(_, button)| *button)
+    }
+}
+
+/// Actions triggered by fixed keys, as opposed to the joypad's rebindable ones.
+/// Letters are only used along with Ctrl, so that they remain free for the joypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Shift + F1-F10.
+    SaveState(u8),
+    /// F1-F10.
+    LoadState(u8),
+    /// Backquote, held.
+    Rewind,
+    /// F12.
+    Screenshot,
+    /// Ctrl + P.
+    Pause,
+    /// Ctrl + N, for "next".
+    Step,
+    /// Ctrl + F.
+    AdvanceFrame,
+    /// Tab, held.
+    FastForward,
+    /// Ctrl + T.
+    ToggleFastForward,
+}
+impl Hotkey {
+    pub fn from_key(key: &str, shift: bool, ctrl: bool) -> Option<Self> {
+        const STATE_KEYS: [Key; 10] = [
+            Key::F1,
+            Key::F2,
+            Key::F3,
+            Key::F4,
+            Key::F5,
+            Key::F6,
+            Key::F7,
+            Key::F8,
+            Key::F9,
+            Key::F10,
+        ];
+
+        if ctrl {
+            match normalise_key(key).as_str() {
+                "p" => return Some(Self::Pause),
+                "n" => return Some(Self::Step),
+                "f" => return Some(Self::AdvanceFrame),
+                "t" => return Some(Self::ToggleFastForward),
+                _ => {}
+            }
+        }
+        if key == "`" {
+            return Some(Self::Rewind);
+        }
+
+        let mut chars = key.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return None;
+        };
+        if c == char::from(Key::F12) {
+            return Some(Self::Screenshot);
+        }
+        if c == char::from(Key::Tab) {
+            return Some(Self::FastForward);
+        }
+        let slot = STATE_KEYS
+            .iter()
+            .position(|state_key| char::from(*state_key) == c)? as u8
+            + 1;
+        Some(if shift {
+            Self::SaveState(slot)
+        } else {
+            Self::LoadState(slot)
+        })
+    }
+}
+
+fn normalise_key(key: &str) -> SharedString {
+    key.to_lowercase().into()
+}
+
+/// Returns a human-readable name for a key.
+pub fn key_label(key: &str) -> String {
+    const NAMED_KEYS: &[(Key, &str)] = &[
+        (Key::Backspace, "Backspace"),
+        (Key::Tab, "Tab"),
+        (Key::Return, "Enter"),
+        (Key::Escape, "Escape"),
+        (Key::Delete, "Delete"),
+        (Key::Shift, "Shift"),
+        (Key::ShiftR, "Right Shift"),
+        (Key::Control, "Ctrl"),
+        (Key::ControlR, "Right Ctrl"),
+        (Key::Alt, "Alt"),
+        (Key::AltGr, "AltGr"),
+        (Key::Meta, "Meta"),
+        (Key::MetaR, "Right Meta"),
+        (Key::Space, "Space"),
+        (Key::UpArrow, "Up"),
+        (Key::DownArrow, "Down"),
+        (Key::LeftArrow, "Left"),
+        (Key::RightArrow, "Right"),
+        (Key::Insert, "Insert"),
+        (Key::Home, "Home"),
+        (Key::End, "End"),
+        (Key::PageUp, "Page Up"),
+        (Key::PageDown, "Page Down"),
+        (Key::F1, "F1"),
+        (Key::F2, "F2"),
+        (Key::F3, "F3"),
+        (Key::F4, "F4"),
+        (Key::F5, "F5"),
+        (Key::F6, "F6"),
+        (Key::F7, "F7"),
+        (Key::F8, "F8"),
+        (Key::F9, "F9"),
+        (Key::F10, "F10"),
+        (Key::F11, "F11"),
+        (Key::F12, "F12"),
+    ];
+
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next())
+        && let Some((_, label)) = NAMED_KEYS.iter().find(|(named, _)| char::from(*named) == c)
+    {
+        return label.to_string();
+    }
+    key.to_uppercase()
+}
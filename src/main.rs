@@ -3,117 +3,1123 @@
 #![allow(dead_code)]
 
 use std::{
+    cell::{Cell, RefCell},
     rc::Rc,
     sync::{
         Arc, Mutex,
-        mpsc::{Receiver, TryRecvError},
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender, TryRecvError},
     },
 };
 
-use slint::{Model, SharedString, VecModel};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
 
 slint::include_modules!();
+mod audio;
+mod headless;
+mod input;
+mod prompt;
+mod recording;
 mod sameboy;
-use sameboy::{DebuggerCmdStr, SameBoy, Schedule};
+mod screenshot;
+mod settings;
+mod states;
+mod testrom;
+mod ui;
+use input::{Button, HeldHotkeys, Hotkey, Joypad, Keymap};
+use prompt::BreakpointCommand;
+use sameboy::{
+    DebuggerCmdStr, DebuggerInput, DebuggerRequest, Halt, MODELS, ModelChoice, Register, SameBoy,
+    Schedule, Speed,
+};
+use settings::Settings;
+use states::StateSlots;
+use ui::{
+    Breakpoints, LogModel, SharedCpuPanel, SharedMemoryViewer, SharedPaletteViewer,
+    SharedSpriteViewer, SharedVramViewer, UiFrontend, push_log_command,
+};
 
 fn main() {
+    let mut args = std::env::args_os().skip(1).peekable();
+    if args.next_if(|arg| arg == "--headless").is_some() {
+        std::process::exit(headless::run(args));
+    }
+
+    let settings = Rc::new(Settings::open().unwrap_or_else(|err| {
+        eprintln!("Unable to open settings database, changes won't be saved: {err}");
+        Settings::in_memory()
+    }));
+    let keymap = Rc::new(RefCell::new(settings.load_keymap().unwrap_or_else(|err| {
+        eprintln!("Unable to load key bindings: {err}");
+        Keymap::default()
+    })));
+
     let main_window = MainWindow::new().expect("Unable to create main window");
     let (debugger_sender, debugger_receiver) = std::sync::mpsc::channel();
     let debugger_window: Rc<DebuggerWindow> =
         Rc::new(DebuggerWindow::new().expect("Unable to create debugger window"));
-    let log_model = Rc::new(VecModel::from(vec![(
-        debugger_window.get_out_color(),
-        SharedString::new(),
-    )]));
-    debugger_window.set_log(log_model.clone().into());
+    debugger_window.set_log(ModelRc::new(LogModel::new()));
+    let history = Rc::new(RefCell::new(prompt::History::new(
+        settings.debugger_history().unwrap_or_else(|err| {
+            eprintln!("Unable to load debugger history: {err}");
+            vec![]
+        }),
+    )));
+    let controls_window: Rc<ControlsWindow> =
+        Rc::new(ControlsWindow::new().expect("Unable to create controls window"));
+    controls_window.set_bindings(bindings_model(&keymap.borrow()));
+    let states_window: Rc<StatesWindow> =
+        Rc::new(StatesWindow::new().expect("Unable to create save states window"));
+    let vram_window: Rc<VramWindow> =
+        Rc::new(VramWindow::new().expect("Unable to create VRAM viewer window"));
+    let vram_viewer = SharedVramViewer::default();
+    let sprites_window: Rc<SpritesWindow> =
+        Rc::new(SpritesWindow::new().expect("Unable to create sprites window"));
+    let sprite_viewer = SharedSpriteViewer::default();
+    let palettes_window: Rc<PalettesWindow> =
+        Rc::new(PalettesWindow::new().expect("Unable to create palettes window"));
+    let palette_viewer = SharedPaletteViewer::default();
+    let memory_window: Rc<MemoryWindow> =
+        Rc::new(MemoryWindow::new().expect("Unable to create memory window"));
+    let memory_viewer = SharedMemoryViewer::default();
+    let cpu_panel = SharedCpuPanel::default();
+    let breakpoints = Breakpoints::default();
+    // The output must be kept alive for as long as we want sound, but no sound is better than no emulator.
+    let (_audio_output, audio_sink) = match audio::open() {
+        Ok((output, sink)) => (Some(output), Some(sink)),
+        Err(err) => {
+            eprintln!("Unable to open audio output: {err}");
+            (None, None)
+        }
+    };
+    let joypad = Arc::new(Joypad::default());
+    let held_hotkeys = Arc::new(HeldHotkeys::default());
     let sameboy = Arc::new(Mutex::new(SameBoy::new(
-        main_window.as_weak(),
+        Box::new(UiFrontend::new(
+            main_window.as_weak(),
+            debugger_window.as_weak(),
+            vram_window.as_weak(),
+            Arc::clone(&vram_viewer),
+            sprites_window.as_weak(),
+            Arc::clone(&sprite_viewer),
+            palettes_window.as_weak(),
+            Arc::clone(&palette_viewer),
+            memory_window.as_weak(),
+            Arc::clone(&memory_viewer),
+            Arc::clone(&cpu_panel),
+            Arc::clone(&breakpoints),
+        )),
         debugger_receiver,
-        debugger_window.as_weak(),
+        audio_sink,
+        Arc::clone(&joypad),
+        Arc::clone(&held_hotkeys),
     )));
+    match settings.save_dir() {
+        Ok(save_dir) => sameboy.lock().unwrap().set_save_dir(save_dir),
+        Err(err) => eprintln!("Unable to load save directory setting: {err}"),
+    }
+    match settings.model() {
+        Ok(model) => {
+            sameboy.lock().unwrap().set_model(model);
+            main_window.set_model_name(model.label().into());
+        }
+        Err(err) => eprintln!("Unable to load model setting: {err}"),
+    }
+    match settings.screenshot_scale() {
+        Ok(scale) => main_window.set_screenshot_scale(scale as i32),
+        Err(err) => eprintln!("Unable to load screenshot scale setting: {err}"),
+    }
+    match settings.rewind_length() {
+        Ok(seconds) => {
+            sameboy.lock().unwrap().set_rewind_length(seconds);
+            main_window.set_rewind_length(seconds as i32);
+        }
+        Err(err) => eprintln!("Unable to load rewind length setting: {err}"),
+    }
+    match settings.log_length() {
+        Ok(lines) => debugger_window.set_log_length(i32::try_from(lines).unwrap_or(i32::MAX)),
+        Err(err) => eprintln!("Unable to load debugger log length setting: {err}"),
+    }
 
-    let (sender, receiver) = std::sync::mpsc::sync_channel(0);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let halt = sameboy.lock().unwrap().halt();
     std::thread::scope(|scope| {
         std::thread::Builder::new()
             .name("GB emulation".into())
             .spawn_scoped(scope, {
                 let sameboy = &sameboy;
-                move || emu_thread_func(sameboy, receiver)
+                let halt = &halt;
+                move || emu_thread_func(sameboy, halt, receiver)
             })
             .expect("Unable to spawn emulation thread");
+        let emulation = Emulation {
+            sameboy: Arc::clone(&sameboy),
+            sender: sender.clone(),
+            debugger_sender: debugger_sender.clone(),
+            halt: Arc::clone(&halt),
+            stops_sent: Rc::new(Cell::new(0)),
+            paused: Rc::new(Cell::new(false)),
+        };
+        let state_slots = StateSlots::new(
+            emulation.clone(),
+            Rc::clone(&settings),
+            main_window.as_weak(),
+            states_window.as_weak(),
+        );
+        state_slots.refresh();
 
         main_window.on_load({
-            let sameboy = Arc::clone(&sameboy);
-            let sender = sender.clone();
+            let emulation = emulation.clone();
+            let main_window = main_window.as_weak();
+            let debugger_window = debugger_window.as_weak();
+            let breakpoints = Arc::clone(&breakpoints);
             move || {
-                if let Some(path) = rfd::FileDialog::new()
+                let Some(path) = rfd::FileDialog::new()
                     .add_filter("Game Boy ROM", &["gb", "gbc"])
                     .pick_file()
+                else {
+                    return;
+                };
+                let loaded = emulation.stopped(|sameboy| {
+                    let other_rom = sameboy.rom_path() != Some(path.as_path());
+                    let result = sameboy.load_rom(&path);
+                    // Breakpoints are meaningless in another ROM.
+                    let breakpoints_deleted = result.is_ok() && other_rom;
+                    if breakpoints_deleted {
+                        sameboy.execute_debugger_command("delete");
+                    }
+                    (result, breakpoints_deleted, sameboy.is_recording_audio())
+                });
+                let (result, breakpoints_deleted, recording_audio) = match loaded {
+                    Ok(loaded) => loaded,
+                    Err(err) => {
+                        show_error("Unable to load ROM", &err);
+                        return;
+                    }
+                };
+                if breakpoints_deleted {
+                    breakpoints.lock().unwrap().clear();
+                    ui::clear_breakpoints(&debugger_window.unwrap());
+                }
+                // Loading a ROM stops audio recording.
+                main_window.unwrap().set_recording_audio(recording_audio);
+                if let Err(err) = result {
+                    show_error("Unable to load ROM", &err);
+                }
+            }
+        });
+        main_window.on_choose_save_dir({
+            let emulation = emulation.clone();
+            let settings = Rc::clone(&settings);
+            move || {
+                if let Some(save_dir) = rfd::FileDialog::new().pick_folder() {
+                    if let Err(err) = settings.set_save_dir(Some(&save_dir)) {
+                        eprintln!("Unable to save save directory setting: {err}");
+                    }
+                    emulation.configure(|sameboy| sameboy.set_save_dir(Some(save_dir)));
+                }
+            }
+        });
+        main_window.on_reset_save_dir({
+            let emulation = emulation.clone();
+            let settings = Rc::clone(&settings);
+            move || {
+                if let Err(err) = settings.set_save_dir(None) {
+                    eprintln!("Unable to save save directory setting: {err}");
+                }
+                emulation.configure(|sameboy| sameboy.set_save_dir(None));
+            }
+        });
+        main_window.on_save_screenshot({
+            let emulation = emulation.clone();
+            let settings = Rc::clone(&settings);
+            move || screenshot::take(&emulation, &settings)
+        });
+        main_window.on_set_screenshot_scale({
+            let settings = Rc::clone(&settings);
+            let main_window = main_window.as_weak();
+            move |scale| {
+                if let Err(err) = settings.set_screenshot_scale(scale as u32) {
+                    eprintln!("Unable to save screenshot scale setting: {err}");
+                }
+                main_window.unwrap().set_screenshot_scale(scale);
+            }
+        });
+        main_window.on_choose_screenshot_dir({
+            let settings = Rc::clone(&settings);
+            move || {
+                if let Some(screenshot_dir) = rfd::FileDialog::new().pick_folder()
+                    && let Err(err) = settings.set_screenshot_dir(Some(&screenshot_dir))
+                {
+                    eprintln!("Unable to save screenshot directory setting: {err}");
+                }
+            }
+        });
+        main_window.on_reset_screenshot_dir({
+            let settings = Rc::clone(&settings);
+            move || {
+                if let Err(err) = settings.set_screenshot_dir(None) {
+                    eprintln!("Unable to save screenshot directory setting: {err}");
+                }
+            }
+        });
+        main_window.on_toggle_recording({
+            let emulation = emulation.clone();
+            let main_window = main_window.as_weak();
+            move || {
+                let main_window = main_window.unwrap();
+                if main_window.get_recording() {
+                    match emulation.stopped(SameBoy::stop_recording) {
+                        Ok(result) => {
+                            if let Err(err) = result {
+                                show_error("Unable to finish recording", &err);
+                            }
+                            main_window.set_recording(false);
+                        }
+                        Err(err) => show_error("Unable to finish recording", &err),
+                    }
+                } else if let Some(path) = rfd::FileDialog::new()
+                    .add_filter(
+                        "YUV4MPEG2 video (audio is saved alongside as WAV)",
+                        &["y4m"],
+                    )
+                    .set_file_name("recording.y4m")
+                    .save_file()
                 {
-                    // If the thread is dead, the locking will fail anyway.
-                    let _ = sender.send(Schedule::Stop);
-                    sameboy.lock().unwrap().load_rom(&path);
-                    // Ditto.
-                    let _ = sender.send(Schedule::Run);
+                    match emulation.stopped(|sameboy| sameboy.start_recording(&path)) {
+                        Ok(Ok(())) => main_window.set_recording(true),
+                        Ok(Err(err)) => show_error("Unable to start recording", &err),
+                        Err(err) => show_error("Unable to start recording", &err),
+                    }
+                }
+            }
+        });
+        main_window.on_toggle_audio_recording({
+            let emulation = emulation.clone();
+            let main_window = main_window.as_weak();
+            move || {
+                let main_window = main_window.unwrap();
+                if main_window.get_recording_audio() {
+                    match emulation.stopped(SameBoy::stop_audio_recording) {
+                        Ok(result) => {
+                            if let Err(err) = result {
+                                show_error("Unable to finish audio recording", &err);
+                            }
+                            main_window.set_recording_audio(false);
+                        }
+                        Err(err) => show_error("Unable to finish audio recording", &err),
+                    }
+                } else if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("WAV audio", &["wav"])
+                    .set_file_name("recording.wav")
+                    .save_file()
+                {
+                    match emulation.stopped(|sameboy| sameboy.start_audio_recording(&path)) {
+                        Ok(Ok(())) => main_window.set_recording_audio(true),
+                        Ok(Err(err)) => show_error("Unable to start audio recording", &err),
+                        Err(err) => show_error("Unable to start audio recording", &err),
+                    }
+                }
+            }
+        });
+        main_window.on_save_state({
+            let state_slots = state_slots.clone();
+            move |slot| state_slots.save(slot as u8)
+        });
+        main_window.on_load_state({
+            let state_slots = state_slots.clone();
+            move |slot| state_slots.load(slot as u8)
+        });
+        states_window.on_save({
+            let state_slots = state_slots.clone();
+            move |slot| state_slots.save(slot as u8)
+        });
+        states_window.on_load({
+            let state_slots = state_slots.clone();
+            move |slot| state_slots.load(slot as u8)
+        });
+        main_window.on_set_rewind_length({
+            let emulation = emulation.clone();
+            let settings = Rc::clone(&settings);
+            let main_window = main_window.as_weak();
+            move |seconds| {
+                if let Err(err) = settings.set_rewind_length(seconds.into()) {
+                    eprintln!("Unable to save rewind length setting: {err}");
+                }
+                emulation.configure(move |sameboy| sameboy.set_rewind_length(seconds.into()));
+                main_window.unwrap().set_rewind_length(seconds);
+            }
+        });
+        main_window.on_set_speed({
+            let emulation = emulation.clone();
+            let main_window = main_window.as_weak();
+            move |percent| {
+                let speed = Speed::from_percent(percent as u32);
+                emulation.configure(move |sameboy| sameboy.set_speed(speed));
+                main_window.unwrap().set_speed(percent);
+            }
+        });
+        main_window.on_toggle_fast_forward({
+            let main_window = main_window.as_weak();
+            // The speed to go back to when toggling fast-forward off.
+            let normal_speed = Cell::new(100);
+            move || {
+                let main_window = main_window.unwrap();
+                match main_window.get_speed() {
+                    0 => main_window.invoke_set_speed(normal_speed.get()),
+                    speed => {
+                        normal_speed.set(speed);
+                        main_window.invoke_set_speed(0);
+                    }
+                }
+            }
+        });
+        main_window.on_set_model({
+            let emulation = emulation.clone();
+            let settings = Rc::clone(&settings);
+            let main_window = main_window.as_weak();
+            move |index| {
+                let model = match usize::try_from(index) {
+                    Ok(index) => ModelChoice::Fixed(MODELS[index].0),
+                    Err(_) => ModelChoice::Auto,
+                };
+                if let Err(err) = settings.set_model(model) {
+                    eprintln!("Unable to save model setting: {err}");
+                }
+                emulation.configure(move |sameboy| sameboy.set_model(model));
+                main_window.unwrap().set_model_name(model.label().into());
+            }
+        });
+        main_window.on_show_states({
+            let states_window = Rc::clone(&states_window);
+            move || {
+                states_window
+                    .show()
+                    .expect("Unable to show save states window");
+            }
+        });
+        main_window.on_hotkey({
+            let state_slots = state_slots.clone();
+            let emulation = emulation.clone();
+            let settings = Rc::clone(&settings);
+            let main_window = main_window.as_weak();
+            move |key, shift, ctrl, pressed, repeat| {
+                let Some(hotkey) = Hotkey::from_key(&key, shift, ctrl) else {
+                    return false;
+                };
+                match hotkey {
+                    Hotkey::Rewind => held_hotkeys.set_rewinding(pressed),
+                    Hotkey::SaveState(slot) if pressed && !repeat => state_slots.save(slot),
+                    Hotkey::LoadState(slot) if pressed && !repeat => state_slots.load(slot),
+                    Hotkey::Screenshot if pressed && !repeat => {
+                        screenshot::take(&emulation, &settings);
+                    }
+                    Hotkey::Pause if pressed && !repeat => {
+                        main_window.unwrap().invoke_toggle_pause()
+                    }
+                    // Holding these down keeps stepping.
+                    Hotkey::Step if pressed => main_window.unwrap().invoke_step(),
+                    Hotkey::AdvanceFrame if pressed => main_window.unwrap().invoke_advance_frame(),
+                    Hotkey::FastForward => held_hotkeys.set_fast_forwarding(pressed),
+                    Hotkey::ToggleFastForward if pressed && !repeat => {
+                        main_window.unwrap().invoke_toggle_fast_forward()
+                    }
+                    _ => {}
+                }
+                true
+            }
+        });
+        main_window.on_key_event({
+            let keymap = Rc::clone(&keymap);
+            move |key, pressed| {
+                let mut handled = false;
+                for button in keymap.borrow().buttons(&key) {
+                    joypad.set(button, pressed);
+                    handled = true;
                 }
+                handled
             }
         });
         main_window.on_quit(|| {
             let _ = slint::quit_event_loop();
         });
-        main_window.on_show_debugger({
-            let debugger_window = Rc::clone(&debugger_window);
+        main_window.on_toggle_pause({
+            let emulation = emulation.clone();
+            let main_window = main_window.as_weak();
+            move || {
+                emulation.set_paused(!emulation.is_halted());
+                main_window.unwrap().set_paused(emulation.is_paused());
+            }
+        });
+        main_window.on_step({
+            let emulation = emulation.clone();
+            let main_window = main_window.as_weak();
             move || {
-                debugger_window
+                emulation.step();
+                main_window.unwrap().set_paused(emulation.is_paused());
+            }
+        });
+        main_window.on_advance_frame({
+            let emulation = emulation.clone();
+            let main_window = main_window.as_weak();
+            move || {
+                // If the debugger stopped emulation, this happens once it resumes.
+                emulation.advance_frame();
+                main_window.unwrap().set_paused(true);
+            }
+        });
+        main_window.on_show_controls({
+            let controls_window = Rc::clone(&controls_window);
+            move || {
+                controls_window
+                    .show()
+                    .expect("Unable to show controls window");
+            }
+        });
+        controls_window.on_bind({
+            let update_keymap = update_keymap(&settings, &keymap, &controls_window);
+            move |index, key| {
+                // The hotkey would take precedence anyway.
+                if Hotkey::from_key(&key, false, false).is_some()
+                    || Hotkey::from_key(&key, true, false).is_some()
+                {
+                    show_error(
+                        "Unable to bind key",
+                        &format!("{} is a hotkey", input::key_label(&key)),
+                    );
+                    return;
+                }
+                update_keymap(&|keymap| keymap.bind(&key, Button::ALL[index as usize]))
+            }
+        });
+        controls_window.on_clear({
+            let update_keymap = update_keymap(&settings, &keymap, &controls_window);
+            move |index| update_keymap(&|keymap| keymap.clear(Button::ALL[index as usize]))
+        });
+        controls_window.on_reset({
+            let update_keymap = update_keymap(&settings, &keymap, &controls_window);
+            move || update_keymap(&|keymap| *keymap = Keymap::default())
+        });
+        main_window.on_show_vram({
+            let vram_window = Rc::clone(&vram_window);
+            let update_vram_viewer = update_vram_viewer(&emulation, &vram_viewer, &vram_window);
+            move || {
+                vram_window
                     .show()
-                    .expect("Unable to show debugger window");
+                    .expect("Unable to show VRAM viewer window");
+                update_vram_viewer();
             }
         });
+        vram_window.on_settings_changed(update_vram_viewer(&emulation, &vram_viewer, &vram_window));
+        hide_viewer_on_close(&*vram_window, {
+            let vram_viewer = Arc::clone(&vram_viewer);
+            move || *vram_viewer.lock().unwrap() = None
+        });
+        main_window.on_show_sprites(show_viewer(&emulation, &sprites_window, &sprite_viewer));
+        sprites_window.on_hover({
+            let main_window = main_window.as_weak();
+            let sprites_window = sprites_window.as_weak();
+            move |_| ui::highlight_hovered_sprite(&main_window.unwrap(), &sprites_window.unwrap())
+        });
+        hide_viewer_on_close(&*sprites_window, {
+            let main_window = main_window.as_weak();
+            let sprite_viewer = Arc::clone(&sprite_viewer);
+            move || {
+                sprite_viewer.store(false, Ordering::Relaxed);
+                // Don't leave a highlight behind.
+                main_window.unwrap().set_highlight(ScreenRect::default());
+            }
+        });
+        main_window.on_show_palettes(show_viewer(&emulation, &palettes_window, &palette_viewer));
+        hide_viewer_on_close(&*palettes_window, {
+            let palette_viewer = Arc::clone(&palette_viewer);
+            move || palette_viewer.store(false, Ordering::Relaxed)
+        });
+        main_window.on_show_memory({
+            let memory_window = Rc::clone(&memory_window);
+            let update_memory_viewer =
+                update_memory_viewer(&emulation, &memory_viewer, &memory_window);
+            move || {
+                memory_window.show().expect("Unable to show memory window");
+                update_memory_viewer();
+            }
+        });
+        memory_window.on_settings_changed({
+            let update_memory_viewer =
+                update_memory_viewer(&emulation, &memory_viewer, &memory_window);
+            let memory_window = memory_window.as_weak();
+            move || {
+                // The selected byte is in another region or bank now.
+                let memory_window = memory_window.unwrap();
+                memory_window.set_selected(-1);
+                memory_window.set_status(SharedString::new());
+                update_memory_viewer();
+            }
+        });
+        memory_window.on_go_to({
+            let update_memory_viewer =
+                update_memory_viewer(&emulation, &memory_viewer, &memory_window);
+            let memory_window = memory_window.as_weak();
+            move |text| {
+                let memory_window = memory_window.unwrap();
+                if ui::memory_go_to(&memory_window, &text) {
+                    memory_window.set_status(SharedString::new());
+                    update_memory_viewer();
+                } else {
+                    memory_window.set_status("Invalid address".into());
+                }
+            }
+        });
+        memory_window.on_scroll({
+            let update_memory_viewer =
+                update_memory_viewer(&emulation, &memory_viewer, &memory_window);
+            let memory_window = memory_window.as_weak();
+            move |pages| {
+                let memory_window = memory_window.unwrap();
+                let start = memory_window.get_start() + pages * sameboy::MEMORY_PAGE_SIZE as i32;
+                memory_window.set_start(start.clamp(0, u16::MAX.into()));
+                update_memory_viewer();
+            }
+        });
+        memory_window.on_write({
+            let emulation = emulation.clone();
+            let memory_window = memory_window.as_weak();
+            move |addr, text| {
+                let memory_window = memory_window.unwrap();
+                let settings = ui::memory_viewer_settings(&memory_window);
+                let status = match ui::parse_hex(&text).and_then(|value| u8::try_from(value).ok()) {
+                    None => "Invalid value",
+                    Some(value) => {
+                        let (reply, replies) = std::sync::mpsc::channel();
+                        emulation.request(DebuggerRequest::WriteMemory {
+                            region: settings.region,
+                            bank: settings.bank,
+                            addr: addr as u16,
+                            value,
+                            reply,
+                        });
+                        if replies.recv().unwrap_or(false) {
+                            ""
+                        } else {
+                            "That address doesn't exist"
+                        }
+                    }
+                };
+                memory_window.set_status(status.into());
+            }
+        });
+        hide_viewer_on_close(&*memory_window, {
+            let memory_viewer = Arc::clone(&memory_viewer);
+            move || *memory_viewer.lock().unwrap() = None
+        });
+        main_window.on_show_debugger(show_viewer(&emulation, &debugger_window, &cpu_panel));
+        main_window.on_load_symbols({
+            let emulation = emulation.clone();
+            move || {
+                let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Symbol file", &["sym"])
+                    .pick_file()
+                else {
+                    return;
+                };
+                let result = emulation.stopped(|sameboy| {
+                    let result = sameboy.load_symbol_file(&path);
+                    // Show the new labels.
+                    sameboy.render_everything();
+                    result
+                });
+                match result {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => show_error("Unable to load symbols", &err),
+                    Err(err) => show_error("Unable to load symbols", &err),
+                }
+            }
+        });
+        debugger_window.on_edit_register({
+            let emulation = emulation.clone();
+            move |index, text| {
+                let register = Register::ALL[index as usize];
+                let Some(value) = ui::parse_hex(&text) else {
+                    show_error(
+                        "Unable to set register",
+                        &format!("\"{text}\" is not a hexadecimal number"),
+                    );
+                    return;
+                };
+                emulation.request(DebuggerRequest::SetRegister(register, value));
+            }
+        });
+        debugger_window.on_toggle_breakpoint({
+            let emulation = emulation.clone();
+            let debugger_window = debugger_window.as_weak();
+            let breakpoints = Arc::clone(&breakpoints);
+            move |addr| {
+                let addr = addr as u16;
+                // The emulation thread locks the set while drawing the disassembly, so it mustn't be held meanwhile.
+                let set = !breakpoints.lock().unwrap().contains(&addr);
+                let command = if set {
+                    format!("breakpoint ${addr:04X}")
+                } else {
+                    format!("delete ${addr:04X}")
+                };
+                emulation.execute_debugger_command(&command);
+                let mut breakpoints = breakpoints.lock().unwrap();
+                if set {
+                    breakpoints.insert(addr);
+                } else {
+                    breakpoints.remove(&addr);
+                }
+                ui::mark_breakpoint(&debugger_window.unwrap(), addr, set);
+            }
+        });
+        hide_viewer_on_close(&*debugger_window, {
+            let cpu_panel = Arc::clone(&cpu_panel);
+            move || cpu_panel.store(false, Ordering::Relaxed)
+        });
         debugger_window.on_submit({
+            let emulation = emulation.clone();
             let debugger_window = Rc::clone(&debugger_window);
+            let breakpoints = Arc::clone(&breakpoints);
             let debugger_sender = debugger_sender.clone();
+            let settings = Rc::clone(&settings);
+            let history = Rc::clone(&history);
             move || {
-                let color = debugger_window.get_cmd_color();
-
-                let command = debugger_window.get_command();
-                let debugger_command = DebuggerCmdStr::new(&command);
-                if log_model
-                    .row_data(log_model.row_count() - 1)
-                    .is_some_and(|row| row.1.is_empty())
+                let mut command = debugger_window.get_command();
+                // Like SameBoy's own command line, an empty line repeats the last command.
+                if command.is_empty()
+                    && let Some(last) = history.borrow().last()
                 {
-                    log_model.set_row_data(log_model.row_count() - 1, (color, command));
-                } else {
-                    log_model.push((color, command));
+                    command = last.into();
+                }
+                if !command.is_empty()
+                    && history.borrow_mut().push(&command)
+                    && let Err(err) = settings.push_debugger_history(&command)
+                {
+                    eprintln!("Unable to save debugger history: {err}");
                 }
-                log_model.push((debugger_window.get_out_color(), SharedString::new()));
+                let debugger_command = DebuggerCmdStr::new(&command);
+                push_log_command(&debugger_window, &command);
+                track_breakpoints(&emulation, &breakpoints, &debugger_window, &command);
                 // It's okay if the other end has hung up.
-                let _ = debugger_sender.send(debugger_command);
+                let _ = debugger_sender.send(DebuggerInput::Command(debugger_command));
                 debugger_window.set_command(SharedString::new());
             }
         });
+        debugger_window.on_search_changed({
+            let debugger_window = debugger_window.as_weak();
+            move || ui::search_log(&debugger_window.unwrap())
+        });
+        debugger_window.on_set_log_length({
+            let debugger_window = debugger_window.as_weak();
+            let settings = Rc::clone(&settings);
+            move |lines| {
+                // The line being printed to is always kept.
+                let lines = u32::try_from(lines).unwrap_or(0).max(1);
+                if let Err(err) = settings.set_log_length(lines) {
+                    eprintln!("Unable to save debugger log length setting: {err}");
+                }
+                let debugger_window = debugger_window.unwrap();
+                debugger_window.set_log_length(i32::try_from(lines).unwrap_or(i32::MAX));
+                ui::trim_log(&debugger_window);
+            }
+        });
+        debugger_window.on_save_log({
+            let debugger_window = debugger_window.as_weak();
+            move || {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Text file", &["txt"])
+                    .set_file_name("debugger.txt")
+                    .save_file()
+                    && let Err(err) = std::fs::write(&path, ui::log_text(&debugger_window.unwrap()))
+                {
+                    show_error("Unable to save log", &err);
+                }
+            }
+        });
+        debugger_window.on_history_older({
+            let debugger_window = debugger_window.as_weak();
+            let history = Rc::clone(&history);
+            move || {
+                let debugger_window = debugger_window.unwrap();
+                if let Some(command) = history.borrow_mut().older(&debugger_window.get_command()) {
+                    debugger_window.set_command(command.into());
+                }
+            }
+        });
+        debugger_window.on_history_newer({
+            let debugger_window = debugger_window.as_weak();
+            move || {
+                if let Some(command) = history.borrow_mut().newer() {
+                    debugger_window.unwrap().set_command(command.into());
+                }
+            }
+        });
+        debugger_window.on_complete({
+            let emulation = emulation.clone();
+            let debugger_window = debugger_window.as_weak();
+            let completion = RefCell::new(None::<prompt::Completion>);
+            move || {
+                let debugger_window = debugger_window.unwrap();
+                let command = debugger_window.get_command();
+                let mut completion = completion.borrow_mut();
+                match &mut *completion {
+                    // Tab was pressed again without typing anything in between.
+                    Some(completion)
+                        if completion.current().as_deref() == Some(command.as_str()) =>
+                    {
+                        completion.advance();
+                    }
+                    _ => {
+                        let suffixes = emulation.completions(&command);
+                        *completion = Some(prompt::Completion::new(command.into(), suffixes));
+                    }
+                }
+                if let Some(completed) = completion.as_ref().and_then(prompt::Completion::current) {
+                    debugger_window.set_command(completed.into());
+                }
+            }
+        });
         main_window.run().expect("Error running application");
 
         // Important to do this before the scope implicitly attempts to join the thread,
         // otherwise we deadlock.
-        let _ = debugger_sender.send(DebuggerCmdStr::new_null()); // Resumes emulation if it was paused in the debugger.
+        let _ = debugger_sender.send(DebuggerInput::Command(DebuggerCmdStr::new_null())); // Resumes emulation if it was paused in the debugger.
         let _ = sender.send(Schedule::Quit); // Tell the emulation thread to shut down.
         drop(sender);
     });
 
+    let mut sameboy = sameboy.lock().unwrap();
+    sameboy.save_battery();
+    if let Err(err) = sameboy.stop_recording() {
+        eprintln!("Unable to finish recording: {err}");
+    }
+    if let Err(err) = sameboy.stop_audio_recording() {
+        eprintln!("Unable to finish audio recording: {err}");
+    }
     // TODO: save application state and all that
 }
 
-fn emu_thread_func(sameboy: &Arc<Mutex<SameBoy>>, receiver: Receiver<Schedule>) {
+/// A handle to the emulation thread, for use by UI callbacks.
+#[derive(Clone)]
+pub struct Emulation {
+    sameboy: Arc<Mutex<SameBoy>>,
+    sender: Sender<Schedule>,
+    debugger_sender: Sender<DebuggerInput>,
+    halt: Arc<Halt>,
+    /// How many [`Schedule::Stop`]s have been sent, to tell when the last one has been acknowledged.
+    stops_sent: Rc<Cell<u64>>,
+    /// Whether the user paused emulation.
+    paused: Rc<Cell<bool>>,
+}
+impl Emulation {
+    /// Stops the emulation thread, runs a function on the emulator, and resumes emulation unless it's paused.
+    /// This is refused while the debugger has stopped emulation, as the emulator is then in the middle of running.
+    pub fn stopped<T>(&self, f: impl FnOnce(&mut SameBoy) -> T) -> Result<T, DebuggerStopped> {
+        let stops = self.stop();
+        let ret = if self.halt.wait(stops, || {}) {
+            Ok(f(&mut self.sameboy.lock().unwrap()))
+        } else {
+            Err(DebuggerStopped)
+        };
+        self.resume();
+        ret
+    }
+
+    /// Like [`Emulation::stopped`], but if the debugger has stopped emulation, the request is handled by it instead.
+    pub fn request(&self, request: DebuggerRequest) {
+        let stops = self.stop();
+        let mut request = Some(request);
+        self.halt.wait(stops, || {
+            // If the emulation thread is dead, the request is dropped along with the error.
+            let _ = self
+                .debugger_sender
+                .send(DebuggerInput::Request(request.take().unwrap()));
+        });
+        if let Some(request) = request {
+            request.handle(&mut self.sameboy.lock().unwrap());
+        }
+        self.resume();
+    }
+
+    /// Redraws the screen and debug views, e.g. because emulation is paused and they must show something else.
+    pub fn render(&self) {
+        self.request(DebuggerRequest::Render);
+    }
+
+    pub fn completions(&self, input: &str) -> Vec<String> {
+        let (reply, replies) = std::sync::mpsc::channel();
+        self.request(DebuggerRequest::Complete(input.to_owned(), reply));
+        // If the emulation thread is dead, there is nothing to complete anyway.
+        replies.recv().unwrap_or_default()
+    }
+
+    pub fn evaluate(&self, expression: &str) -> Option<u16> {
+        let (reply, replies) = std::sync::mpsc::channel();
+        self.request(DebuggerRequest::Evaluate(expression.to_owned(), reply));
+        replies.recv().ok().flatten()
+    }
+
+    /// Runs a debugger command right away, even if emulation is paused.
+    pub fn execute_debugger_command(&self, command: &str) {
+        if self
+            .stopped(|sameboy| sameboy.execute_debugger_command(command))
+            .is_err()
+        {
+            // The debugger is waiting for a command anyway. If the thread is dead, there's nothing to run it on.
+            let _ = self
+                .debugger_sender
+                .send(DebuggerInput::Command(DebuggerCmdStr::new(command)));
+        }
+    }
+
+    /// Has the emulation thread change a setting as soon as it can, even if the debugger has stopped emulation.
+    pub fn configure(&self, f: impl FnOnce(&mut SameBoy) + Send + 'static) {
+        // If the thread is dead, there's nothing to configure anyway.
+        let _ = self.sender.send(Schedule::Configure(Box::new(f)));
+    }
+
+    /// Sends a [`Schedule::Stop`], and returns how many have been sent in total.
+    fn stop(&self) -> u64 {
+        // If the thread is dead, waiting for the acknowledgement will fail anyway.
+        let _ = self.sender.send(Schedule::Stop);
+        self.stops_sent.set(self.stops_sent.get() + 1);
+        self.stops_sent.get()
+    }
+
+    fn resume(&self) {
+        if !self.paused.get() {
+            // If the thread is dead, there's nothing to resume anyway.
+            let _ = self.sender.send(Schedule::Run);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.get()
+    }
+
+    /// Resuming also makes the debugger continue, if it stopped emulation.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.set(paused);
+        if paused {
+            self.stop();
+        } else {
+            self.resume();
+        }
+        if !paused && self.halt.is_debugger_stopped() {
+            // If the thread is dead, there's nothing to resume anyway.
+            let _ = self
+                .debugger_sender
+                .send(DebuggerInput::Command(DebuggerCmdStr::new("continue")));
+        }
+    }
+
+    /// Whether emulation is either paused, or stopped by the debugger.
+    pub fn is_halted(&self) -> bool {
+        self.paused.get() || self.halt.is_debugger_stopped()
+    }
+
+    /// Pauses emulation if it wasn't already, and runs a single instruction.
+    /// If the debugger stopped emulation, this is its `step` command instead.
+    pub fn step(&self) {
+        if self.halt.is_debugger_stopped() {
+            // If the thread is dead, there's nothing to run anyway.
+            let _ = self
+                .debugger_sender
+                .send(DebuggerInput::Command(DebuggerCmdStr::new("step")));
+            return;
+        }
+        self.paused.set(true);
+        // Ditto.
+        let _ = self.sender.send(Schedule::Step);
+    }
+
+    /// Pauses emulation if it wasn't already, and runs until the next frame.
+    pub fn advance_frame(&self) {
+        self.paused.set(true);
+        // If the thread is dead, there's nothing to run anyway.
+        let _ = self.sender.send(Schedule::RunFrame);
+    }
+}
+
+/// Returned when something can't be done while the debugger has stopped emulation.
+#[derive(Debug)]
+pub struct DebuggerStopped;
+impl std::fmt::Display for DebuggerStopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The debugger has stopped emulation; continue it first.")
+    }
+}
+impl std::error::Error for DebuggerStopped {}
+
+/// Reports an error to the user.
+pub fn show_error(title: &str, err: &dyn std::fmt::Display) {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title(title)
+        .set_description(err.to_string())
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+
+/// Returns a function that modifies the keymap, and then persists the changes and refreshes the controls window.
+fn update_keymap(
+    settings: &Rc<Settings>,
+    keymap: &Rc<RefCell<Keymap>>,
+    controls_window: &Rc<ControlsWindow>,
+) -> impl Fn(&dyn Fn(&mut Keymap)) + use<> {
+    let settings = Rc::clone(settings);
+    let keymap = Rc::clone(keymap);
+    let controls_window = Rc::downgrade(controls_window);
+    move |update| {
+        let mut keymap = keymap.borrow_mut();
+        update(&mut keymap);
+        if let Err(err) = settings.save_keymap(&keymap) {
+            eprintln!("Unable to save key bindings: {err}");
+        }
+        if let Some(controls_window) = controls_window.upgrade() {
+            controls_window.set_bindings(bindings_model(&keymap));
+        }
+    }
+}
+
+/// Returns a function that shows a debug view's window, and has the view drawn from then on.
+fn show_viewer<W: ComponentHandle + 'static>(
+    emulation: &Emulation,
+    window: &Rc<W>,
+    shown: &Arc<AtomicBool>,
+) -> impl Fn() + use<W> {
+    let emulation = emulation.clone();
+    let window = Rc::clone(window);
+    let shown = Arc::clone(shown);
+    move || {
+        window.show().expect("Unable to show window");
+        shown.store(true, Ordering::Relaxed);
+        // Draw it right away, in case emulation is paused.
+        emulation.render();
+    }
+}
+
+/// Hides a debug view's window instead of destroying it when it's closed, and calls `hide` to stop drawing the
+/// view for nothing.
+fn hide_viewer_on_close(window: &impl ComponentHandle, hide: impl Fn() + 'static) {
+    window.window().on_close_requested(move || {
+        hide();
+        slint::CloseRequestResponse::HideWindow
+    });
+}
+
+/// Returns a function that applies the VRAM viewer window's settings, and redraws it.
+fn update_vram_viewer(
+    emulation: &Emulation,
+    vram_viewer: &SharedVramViewer,
+    vram_window: &Rc<VramWindow>,
+) -> impl Fn() + use<> {
+    let emulation = emulation.clone();
+    let vram_viewer = Arc::clone(vram_viewer);
+    let vram_window = Rc::downgrade(vram_window);
+    move || {
+        if let Some(vram_window) = vram_window.upgrade() {
+            *vram_viewer.lock().unwrap() = Some(ui::vram_viewer_settings(&vram_window));
+            // Redraw right away, in case emulation is paused.
+            emulation.render();
+        }
+    }
+}
+
+/// Returns a function that makes the memory viewer show what its window selects, and redraws it.
+fn update_memory_viewer(
+    emulation: &Emulation,
+    memory_viewer: &SharedMemoryViewer,
+    memory_window: &Rc<MemoryWindow>,
+) -> impl Fn() + use<> {
+    let emulation = emulation.clone();
+    let memory_viewer = Arc::clone(memory_viewer);
+    let memory_window = Rc::downgrade(memory_window);
+    move || {
+        if let Some(memory_window) = memory_window.upgrade() {
+            *memory_viewer.lock().unwrap() = Some(ui::memory_viewer_settings(&memory_window));
+            // Redraw right away, in case emulation is paused.
+            emulation.render();
+        }
+    }
+}
+
+/// Keeps the breakpoint markers in sync with a debugger command that the user typed, if it changes breakpoints.
+fn track_breakpoints(
+    emulation: &Emulation,
+    breakpoints: &Breakpoints,
+    debugger_window: &DebuggerWindow,
+    command: &str,
+) {
+    let Some(command) = BreakpointCommand::parse(command) else {
+        return;
+    };
+    // The set is only locked after evaluating the expression, as the emulation thread locks it while drawing
+    // the disassembly.
+    match command {
+        BreakpointCommand::DeleteAll => {
+            breakpoints.lock().unwrap().clear();
+            ui::clear_breakpoints(debugger_window);
+        }
+        BreakpointCommand::Set(expression) | BreakpointCommand::Delete(expression) => {
+            // If the expression is invalid, SameBoy will report it when running the command.
+            let Some(addr) = emulation.evaluate(expression) else {
+                return;
+            };
+            let set = matches!(command, BreakpointCommand::Set(_));
+            let mut breakpoints = breakpoints.lock().unwrap();
+            if set {
+                breakpoints.insert(addr);
+            } else {
+                breakpoints.remove(&addr);
+            }
+            ui::mark_breakpoint(debugger_window, addr, set);
+        }
+    }
+}
+
+/// Formats the keymap for display in the controls window, in the same order as [`Button::ALL`].
+fn bindings_model(keymap: &Keymap) -> ModelRc<(SharedString, SharedString)> {
+    let rows: Vec<_> = Button::ALL
+        .into_iter()
+        .map(|button| {
+            let keys: Vec<_> = keymap
+                .keys(button)
+                .map(|key| input::key_label(key))
+                .collect();
+            (button.label().into(), keys.join(", ").into())
+        })
+        .collect();
+    ModelRc::new(VecModel::from(rows))
+}
+
+/// Lets the UI thread know that the emulation thread has exited, even if it panicked.
+struct EndGuard<'a>(&'a Halt);
+impl Drop for EndGuard<'_> {
+    fn drop(&mut self) {
+        self.0.end();
+    }
+}
+
+fn emu_thread_func(sameboy: &Arc<Mutex<SameBoy>>, halt: &Halt, receiver: Receiver<Schedule>) {
+    let _guard = EndGuard(halt);
     while let Ok(mut schedule) = receiver.recv() {
         'schedule: loop {
             match schedule {
-                Schedule::Stop => {}
+                Schedule::Stop => {
+                    // Commands sent in the meantime would otherwise wait until emulation resumes.
+                    sameboy.lock().unwrap().handle_debugger_input();
+                    halt.acknowledge_stop();
+                }
+                Schedule::Configure(f) => f(&mut sameboy.lock().unwrap()),
                 Schedule::Run => {
                     let mut sameboy = sameboy.lock().unwrap();
                     loop {
                         match receiver.try_recv() {
                             Err(TryRecvError::Disconnected) => return,
                             Err(TryRecvError::Empty) => {}
+                            // This doesn't interrupt emulation.
+                            Ok(Schedule::Configure(f)) => f(&mut sameboy),
                             Ok(new_schedule) => {
                                 schedule = new_schedule;
                                 continue 'schedule;
@@ -125,10 +1131,13 @@ fn emu_thread_func(sameboy: &Arc<Mutex<SameBoy>>, receiver: Receiver<Schedule>)
                 Schedule::Step => {
                     let mut sameboy = sameboy.lock().unwrap();
                     sameboy.step();
+                    // Otherwise, the debug views would only update on the next VBlank.
+                    sameboy.render_everything();
                 }
                 Schedule::RunFrame => {
                     let mut sameboy = sameboy.lock().unwrap();
                     sameboy.run_frame();
+                    sameboy.render_everything();
                 }
 
                 Schedule::Quit => return,
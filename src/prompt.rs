@@ -0,0 +1,270 @@
+//! The debugger's command line: history navigation, Tab completion, and spotting breakpoint commands.
+
+/// How many commands are remembered.
+pub const HISTORY_LENGTH: usize = 1000;
+
+/// The commands submitted to the debugger, browsed with the Up and Down keys.
+#[derive(Debug, Default)]
+pub struct History {
+    /// Oldest first.
+    entries: Vec<String>,
+    /// Which entry is in the command line, or `entries.len()` if none is.
+    position: usize,
+    /// What was typed before browsing the history, so that it can be brought back.
+    draft: String,
+}
+
+impl History {
+    pub fn new(mut entries: Vec<String>) -> Self {
+        if entries.len() > HISTORY_LENGTH {
+            entries.drain(..entries.len() - HISTORY_LENGTH);
+        }
+        Self {
+            position: entries.len(),
+            entries,
+            draft: String::new(),
+        }
+    }
+
+    /// The last command that was submitted.
+    pub fn last(&self) -> Option<&str> {
+        self.entries.last().map(String::as_str)
+    }
+
+    /// Records a submitted command, and stops browsing.
+    /// Returns `false` if the command was not added, because it's the same as the last one.
+    pub fn push(&mut self, command: &str) -> bool {
+        let is_new = self.last() != Some(command);
+        if is_new {
+            if self.entries.len() == HISTORY_LENGTH {
+                self.entries.remove(0);
+            }
+            self.entries.push(command.to_owned());
+        }
+        self.position = self.entries.len();
+        self.draft.clear();
+        is_new
+    }
+
+    /// Returns the command before the one in the command line, if any; `current` is what's in the command line.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        if self.position == 0 {
+            return None;
+        }
+        if self.position == self.entries.len() {
+            self.draft = current.to_owned();
+        }
+        self.position -= 1;
+        Some(&self.entries[self.position])
+    }
+
+    /// Returns the command after the one in the command line, or what had been typed if there is none.
+    pub fn newer(&mut self) -> Option<&str> {
+        if self.position == self.entries.len() {
+            return None;
+        }
+        self.position += 1;
+        Some(self.entries.get(self.position).unwrap_or(&self.draft))
+    }
+}
+
+/// Cycles through the completions of a partially typed command, one per press of Tab.
+#[derive(Debug)]
+pub struct Completion {
+    input: String,
+    /// What can be appended to the input.
+    suffixes: Vec<String>,
+    index: usize,
+}
+
+impl Completion {
+    pub fn new(input: String, suffixes: Vec<String>) -> Self {
+        Self {
+            input,
+            suffixes,
+            index: 0,
+        }
+    }
+
+    /// The completed command, or `None` if there are no completions.
+    pub fn current(&self) -> Option<String> {
+        let suffix = self.suffixes.get(self.index)?;
+        Some(format!("{}{suffix}", self.input))
+    }
+
+    /// Moves on to the next completion, wrapping around.
+    pub fn advance(&mut self) {
+        if !self.suffixes.is_empty() {
+            self.index = (self.index + 1) % self.suffixes.len();
+        }
+    }
+}
+
+/// A command that changes the breakpoints, which the disassembly pane shows.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BreakpointCommand<'a> {
+    /// Sets a breakpoint at the address that the expression evaluates to.
+    Set(&'a str),
+    /// Deletes the breakpoint at the address that the expression evaluates to.
+    Delete(&'a str),
+    DeleteAll,
+}
+
+impl<'a> BreakpointCommand<'a> {
+    pub fn parse(command: &'a str) -> Option<Self> {
+        let command = command.trim();
+        let (name, arguments) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        // Like SameBoy, accept abbreviations down to a minimum length.
+        let is = |full: &str, min_len| name.len() >= min_len && full.starts_with(name);
+        // Only the address matters: not the condition, nor whether the breakpoint is on jumps to it.
+        let address = arguments.split(" if ").next().unwrap_or_default().trim();
+        let address = address.strip_suffix(" to").unwrap_or(address).trim_end();
+
+        if is("breakpoint", 1) && !address.is_empty() {
+            Some(Self::Set(address))
+        } else if is("delete", 2) {
+            Some(if address.is_empty() {
+                Self::DeleteAll
+            } else {
+                Self::Delete(address)
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        History::new(entries.iter().map(|&entry| entry.to_owned()).collect())
+    }
+
+    #[test]
+    fn history_browsing_restores_draft() {
+        let mut history = history(&["step", "next"]);
+        assert_eq!(history.older("print a"), Some("next"));
+        assert_eq!(history.older("next"), Some("step"));
+        assert_eq!(history.older("step"), None);
+        assert_eq!(history.newer(), Some("next"));
+        assert_eq!(history.newer(), Some("print a"));
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn history_push_stops_browsing() {
+        let mut history = history(&["step", "next"]);
+        history.older("print a");
+        history.older("next");
+        assert!(history.push("finish"));
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older("print b"), Some("finish"));
+        assert_eq!(history.newer(), Some("print b"));
+    }
+
+    #[test]
+    fn history_skips_repeats() {
+        let mut history = history(&["step"]);
+        assert!(!history.push("step"));
+        assert!(history.push("next"));
+        assert!(history.push("step"));
+        assert_eq!(history.entries, ["step", "next", "step"]);
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let entries: Vec<_> = (0..HISTORY_LENGTH + 5).map(|i| i.to_string()).collect();
+        let mut history = History::new(entries);
+        assert_eq!(history.entries.len(), HISTORY_LENGTH);
+        assert_eq!(history.entries[0], "5");
+
+        assert!(history.push("step"));
+        assert_eq!(history.entries.len(), HISTORY_LENGTH);
+        assert_eq!(history.entries[0], "6");
+        assert_eq!(history.last(), Some("step"));
+    }
+
+    #[test]
+    fn completion_wraps_around() {
+        let mut completion = Completion::new(
+            "br".into(),
+            vec!["eakpoint".into(), "eak".into(), "".into()],
+        );
+        assert_eq!(completion.current().as_deref(), Some("breakpoint"));
+        completion.advance();
+        assert_eq!(completion.current().as_deref(), Some("break"));
+        completion.advance();
+        assert_eq!(completion.current().as_deref(), Some("br"));
+        completion.advance();
+        assert_eq!(completion.current().as_deref(), Some("breakpoint"));
+    }
+
+    #[test]
+    fn completion_without_suffixes() {
+        let mut completion = Completion::new("xyz".into(), vec![]);
+        assert_eq!(completion.current(), None);
+        completion.advance();
+        assert_eq!(completion.current(), None);
+    }
+
+    #[test]
+    fn parse_set() {
+        use BreakpointCommand::Set;
+        assert_eq!(
+            BreakpointCommand::parse("breakpoint $150"),
+            Some(Set("$150"))
+        );
+        assert_eq!(
+            BreakpointCommand::parse("  b   Main.loop  "),
+            Some(Set("Main.loop"))
+        );
+        assert_eq!(BreakpointCommand::parse("br $150"), Some(Set("$150")));
+        assert_eq!(BreakpointCommand::parse("breakpoint"), None);
+        assert_eq!(BreakpointCommand::parse("b"), None);
+    }
+
+    #[test]
+    fn parse_set_ignores_condition_and_jumps() {
+        use BreakpointCommand::Set;
+        assert_eq!(
+            BreakpointCommand::parse("b $150 if a == 3"),
+            Some(Set("$150"))
+        );
+        assert_eq!(BreakpointCommand::parse("b $150 to"), Some(Set("$150")));
+        assert_eq!(
+            BreakpointCommand::parse("b $150 to if a == 3"),
+            Some(Set("$150"))
+        );
+        assert_eq!(
+            BreakpointCommand::parse("b hl + 2 if a == 3"),
+            Some(Set("hl + 2"))
+        );
+    }
+
+    #[test]
+    fn parse_delete() {
+        use BreakpointCommand::{Delete, DeleteAll};
+        assert_eq!(
+            BreakpointCommand::parse("delete $150"),
+            Some(Delete("$150"))
+        );
+        assert_eq!(BreakpointCommand::parse("de $150"), Some(Delete("$150")));
+        assert_eq!(BreakpointCommand::parse("delete"), Some(DeleteAll));
+        assert_eq!(BreakpointCommand::parse("de"), Some(DeleteAll));
+        // Too short to tell apart from other commands.
+        assert_eq!(BreakpointCommand::parse("d $150"), None);
+        assert_eq!(BreakpointCommand::parse("d"), None);
+    }
+
+    #[test]
+    fn parse_other_commands() {
+        assert_eq!(BreakpointCommand::parse("backtrace"), None);
+        assert_eq!(BreakpointCommand::parse("bank $150"), None);
+        assert_eq!(BreakpointCommand::parse("deletes"), None);
+        assert_eq!(BreakpointCommand::parse(""), None);
+    }
+}
@@ -0,0 +1,139 @@
+//! Recording gameplay to uncompressed files: Y4M for video, and WAV for audio.
+//!
+//! Both are written from the emulation thread, as frames and samples are produced, so they stay in sync
+//! regardless of how fast emulation runs. Frame skipping must be off while recording, so that no frame
+//! is missed in turbo mode.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// The Game Boy's frame rate, as a fraction: 4 MiHz divided by 70224 cycles per frame.
+const FRAME_RATE: (u32, u32) = (262144, 4389);
+
+pub struct Recorder {
+    video: BufWriter<File>,
+    width: u32,
+    height: u32,
+    /// Scratch buffer for the frame being converted, to avoid reallocating it every frame.
+    planes: Vec<u8>,
+    audio: WavWriter,
+}
+
+impl Recorder {
+    /// Starts writing video to `video_path`, and audio next to it with a `.wav` extension.
+    pub fn start(video_path: &Path, width: u32, height: u32, sample_rate: u32) -> io::Result<Self> {
+        let mut video = BufWriter::new(File::create(video_path)?);
+        let (num, den) = FRAME_RATE;
+        // The Game Boy's pixels are square, and we use 4:4:4 subsampling so that colours don't bleed.
+        writeln!(
+            video,
+            "YUV4MPEG2 W{width} H{height} F{num}:{den} Ip A1:1 C444",
+        )?;
+        Ok(Self {
+            video,
+            width,
+            height,
+            planes: Vec::with_capacity((width * height * 3) as usize),
+            audio: WavWriter::new(&Self::audio_path(video_path), sample_rate)?,
+        })
+    }
+
+    /// Where the audio goes when recording video to `video_path`.
+    pub fn audio_path(video_path: &Path) -> PathBuf {
+        video_path.with_extension("wav")
+    }
+
+    /// The size that frames must be, in pixels.
+    pub fn frame_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Appends a frame; the pixels must be in the format the framebuffer uses (RGBA bytes), and there must be
+    /// as many as [`Recorder::frame_size`] says.
+    pub fn push_frame(&mut self, pixels: &[u32]) -> io::Result<()> {
+        if pixels.len() != (self.width * self.height) as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the frame isn't the size the recording started with",
+            ));
+        }
+        self.planes.clear();
+        // BT.601 limited range, which is what players expect when the header doesn't say otherwise.
+        let rgb = || {
+            pixels.iter().map(|pixel| {
+                let [r, g, b, _] = pixel.to_ne_bytes();
+                [i32::from(r), i32::from(g), i32::from(b)]
+            })
+        };
+        self.planes
+            .extend(rgb().map(|[r, g, b]| (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8));
+        self.planes
+            .extend(rgb().map(|[r, g, b]| (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8));
+        self.planes
+            .extend(rgb().map(|[r, g, b]| (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8));
+        self.video.write_all(b"FRAME\n")?;
+        self.video.write_all(&self.planes)
+    }
+
+    pub fn push_sample(&mut self, sample: [i16; 2]) -> io::Result<()> {
+        self.audio.push(sample)
+    }
+
+    /// Flushes everything to disk; the files are incomplete unless this is called.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.video.flush()?;
+        self.audio.finish()
+    }
+}
+
+/// Writes 16-bit stereo PCM samples to a WAV file.
+struct WavWriter {
+    file: BufWriter<File>,
+    nb_samples: u32,
+}
+
+impl WavWriter {
+    const HEADER_LEN: u32 = 44;
+    const BYTES_PER_SAMPLE: u32 = 2 * 2;
+
+    fn new(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        // The sizes are left at 0 until `finish` fills them in.
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?; // Size of the `fmt ` chunk.
+        file.write_all(&1u16.to_le_bytes())?; // PCM.
+        file.write_all(&2u16.to_le_bytes())?; // Channels.
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * Self::BYTES_PER_SAMPLE).to_le_bytes())?; // Byte rate.
+        file.write_all(&(Self::BYTES_PER_SAMPLE as u16).to_le_bytes())?; // Block alignment.
+        file.write_all(&16u16.to_le_bytes())?; // Bits per sample.
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            file,
+            nb_samples: 0,
+        })
+    }
+
+    fn push(&mut self, [left, right]: [i16; 2]) -> io::Result<()> {
+        self.file.write_all(&left.to_le_bytes())?;
+        self.file.write_all(&right.to_le_bytes())?;
+        self.nb_samples += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let data_len = self.nb_samples * Self::BYTES_PER_SAMPLE;
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(Self::HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&data_len.to_le_bytes())?;
+        self.file.flush()
+    }
+}
@@ -6,33 +6,148 @@
 #![allow(improper_ctypes)]
 
 use std::{
-    ffi::{CStr, c_char, c_void},
+    collections::VecDeque,
+    ffi::{CStr, CString, c_char, c_void},
+    fmt::Display,
+    io::{self, Write},
     mem::{ManuallyDrop, MaybeUninit},
-    path::Path,
-    sync::mpsc::{Receiver, RecvError, TryRecvError},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex,
+        mpsc::{Receiver, RecvError, Sender, TryRecvError},
+    },
 };
 
-use slint::{Brush, Model, SharedPixelBuffer, SharedString, VecModel, Weak};
+use slint::{Rgba8Pixel, SharedPixelBuffer};
 
-use crate::{DebuggerWindow, MainWindow};
+use crate::{
+    audio::AudioSink,
+    input::{Button, HeldHotkeys, Joypad, JoypadState},
+    recording::Recorder,
+    testrom::{self, TestMonitor},
+};
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+// Defined in `sameboy_internals.c`.
+unsafe extern "C" {
+    fn aythya_get_ime(gb: *mut GB_gameboy_t) -> bool;
+    fn aythya_is_halted(gb: *mut GB_gameboy_t) -> bool;
+}
+
+/// Whatever the emulator's output goes to; this keeps the emulator itself independent from the UI.
+///
+/// Debug views (the VRAM, sprite, palette and memory viewers, and the CPU state panel) are only drawn while the
+/// frontend says they're shown. Their `update_*` method is then called on every VBlank, and whenever the screen
+/// must otherwise be redrawn (see [`SameBoy::render_everything`]).
+pub trait Frontend: Send {
+    /// Called on every VBlank, and whenever the screen must otherwise be redrawn.
+    fn update_screen(&mut self, screen: SharedPixelBuffer<Rgba8Pixel>);
+    /// Called with text printed by SameBoy, mostly by the debugger.
+    fn log(&mut self, text: &str, attributes: GB_log_attributes_t);
+    /// Called when recording had to stop due to an error.
+    fn recording_aborted(&mut self, err: io::Error);
+
+    /// What the VRAM viewer should show, or `None` if it's hidden.
+    fn vram_viewer(&self) -> Option<VramViewerSettings> {
+        None
+    }
+    fn update_vram_viewer(&mut self, _images: VramImages) {}
+
+    /// Whether the sprite viewer is shown.
+    fn sprite_viewer_shown(&self) -> bool {
+        false
+    }
+    fn update_sprite_viewer(&mut self, _objects: Objects) {}
+
+    /// Whether the palette viewer is shown.
+    fn palette_viewer_shown(&self) -> bool {
+        false
+    }
+    fn update_palette_viewer(&mut self, _palettes: Palettes) {}
+
+    /// What the memory viewer should show, or `None` if it's hidden.
+    fn memory_viewer(&self) -> Option<MemoryViewerSettings> {
+        None
+    }
+    fn update_memory_viewer(&mut self, _page: MemoryPage) {}
+
+    /// Whether the CPU state panel is shown.
+    fn cpu_panel_shown(&self) -> bool {
+        false
+    }
+    /// Also called whenever the debugger stops emulation, even if the panel is hidden.
+    fn update_cpu_panel(&mut self, _state: CpuState) {}
+    /// Called when the debugger stops emulation to wait for a command (with `true`), and when it gets one.
+    fn debugger_stopped(&mut self, _stopped: bool) {}
+    /// Called whenever the debugger stops emulation, and when a register is edited meanwhile, with the code
+    /// starting at PC.
+    fn update_disassembly(&mut self, _lines: Vec<DisassemblyLine>) {}
+}
+
 #[repr(C)] // Necessary to cast the pointer to its `gb` member back to a pointer to the struct itself.
 pub struct SameBoy {
     gb: GB_gameboy_t,
     framebuffer: Vec<u32>,
-    main_window: Weak<MainWindow>,
-    debugger_receiver: Receiver<DebuggerCmdStr>,
-    debugger_window: Weak<DebuggerWindow>,
+    /// The framebuffer as of the last VBlank; unlike the framebuffer itself, it's never halfway drawn.
+    last_frame: SharedPixelBuffer<Rgba8Pixel>,
+    frontend: Box<dyn Frontend>,
+    debugger_receiver: Receiver<DebuggerInput>,
+    /// Tells the UI thread when the debugger holds onto the emulator; see [`Halt`].
+    halt: Arc<Halt>,
+    /// Commands that arrived while answering late requests, to be given to SameBoy before any others.
+    deferred_commands: VecDeque<DebuggerCmdStr>,
+    audio: Option<AudioSink>,
+    /// The rate at which SameBoy produces samples, even if they aren't played.
+    sample_rate: u32,
+    recorder: Option<Recorder>,
+    /// Whether SameBoy's own audio recorder is running.
+    recording_audio: bool,
+    joypad: Arc<Joypad>,
+    /// The joypad state that was last passed to SameBoy.
+    applied_joypad: JoypadState,
+    held_hotkeys: Arc<HeldHotkeys>,
+    /// Set when a frame has been completed while rewinding; see [`SameBoy::run_once`].
+    rewind_pending: bool,
+    model_choice: ModelChoice,
+    /// Path to the currently loaded ROM, if any.
+    rom_path: Option<PathBuf>,
+    /// A symbol file loaded by hand for the current ROM, which is reloaded along with it.
+    symbol_file: Option<PathBuf>,
+    /// If set, battery saves are stored in this directory instead of next to the ROM.
+    save_dir: Option<PathBuf>,
+    /// If not, battery saves are neither loaded nor written, and cartridge RAM starts out blank.
+    battery_saves: bool,
+    /// The battery save's contents as of the last time it was written, to avoid needless writes.
+    last_battery_save: Vec<u8>,
+    frames_since_battery_save: u32,
+    /// Only present in headless test mode.
+    test_monitor: Option<TestMonitor>,
+    /// While set, SameBoy's output goes there instead of to the frontend; see [`SameBoy::disassemble`].
+    log_capture: Option<String>,
+    speed: Speed,
+    /// The speed that was last passed to SameBoy, and whether video and audio were being recorded at the time;
+    /// `None` if it must be passed again.
+    applied_speed: Option<(Speed, bool, bool)>,
+    /// Whether audio output is muted because emulation isn't running at normal speed.
+    muted: bool,
 }
 
+/// The sample rate used when there is no audio output, for the sake of recording.
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// How often the battery save is written back while running, in frames (about a minute).
+const BATTERY_SAVE_INTERVAL: u32 = 60 * 60;
+
 /// Creation.
 impl SameBoy {
     pub fn new(
-        main_window: Weak<MainWindow>,
-        debugger_receiver: Receiver<DebuggerCmdStr>,
-        debugger_window: Weak<DebuggerWindow>,
+        frontend: Box<dyn Frontend>,
+        debugger_receiver: Receiver<DebuggerInput>,
+        audio: Option<AudioSink>,
+        joypad: Arc<Joypad>,
+        held_hotkeys: Arc<HeldHotkeys>,
     ) -> Self {
         let mut emu = MaybeUninit::uninit();
         // SAFETY: this very function is responsible for initialising the struct.
@@ -49,13 +164,47 @@ impl SameBoy {
         unsafe { GB_set_async_input_callback(emu.as_mut_ptr(), Some(Self::async_input_callback)) };
         // SAFETY: ditto.
         unsafe { GB_set_log_callback(emu.as_mut_ptr(), Some(Self::log_callback)) };
+        // Samples are produced even without audio output, since they may be recorded.
+        let sample_rate = audio
+            .as_ref()
+            .map_or(DEFAULT_SAMPLE_RATE, AudioSink::sample_rate);
+        // SAFETY: ditto.
+        unsafe { GB_set_sample_rate(emu.as_mut_ptr(), sample_rate) };
+        // SAFETY: ditto.
+        unsafe {
+            GB_set_highpass_filter_mode(emu.as_mut_ptr(), GB_highpass_mode_t_GB_HIGHPASS_ACCURATE)
+        };
+        // SAFETY: ditto.
+        unsafe { GB_apu_set_sample_callback(emu.as_mut_ptr(), Some(Self::sample_callback)) };
         let mut this = Self {
             // SAFETY: the above call initialised the struct.
             gb: unsafe { emu.assume_init() },
             framebuffer: vec![],
-            main_window,
+            last_frame: SharedPixelBuffer::new(0, 0),
+            frontend,
             debugger_receiver,
-            debugger_window,
+            halt: Arc::default(),
+            deferred_commands: VecDeque::new(),
+            audio,
+            sample_rate,
+            recorder: None,
+            recording_audio: false,
+            joypad,
+            applied_joypad: JoypadState::default(),
+            held_hotkeys,
+            rewind_pending: false,
+            model_choice: ModelChoice::Fixed(GB_model_t_GB_MODEL_DMG_B),
+            rom_path: None,
+            save_dir: None,
+            battery_saves: true,
+            last_battery_save: vec![],
+            frames_since_battery_save: 0,
+            test_monitor: None,
+            log_capture: None,
+            symbol_file: None,
+            speed: Speed::NORMAL,
+            applied_speed: None,
+            muted: false,
         };
         this.resize_framebuffer();
         this
@@ -77,8 +226,20 @@ impl SameBoy {
         // SAFETY: this is called on an instance initialised by the constructor.
         let height = unsafe { GB_get_screen_height(&mut self.gb) };
         self.framebuffer.resize((width * height) as usize, 0);
+        // Screenshots and state thumbnails use the last frame until the next one is complete, so it's only
+        // replaced if it can't match the screen anymore.
+        if (self.last_frame.width(), self.last_frame.height()) != (width, height) {
+            self.last_frame = self.live_pixels();
+        }
         // SAFETY: the pointer will remain unmodified by `self`, except by calls to `resize_framebuffer`, which refresh it.
         unsafe { GB_set_pixels_output(&mut self.gb, self.framebuffer.as_mut_ptr()) };
+        if let Some(recorder) = &self.recorder
+            && recorder.frame_size() != (width, height)
+        {
+            self.abort_recording(io::Error::other(
+                "the screen size changed, which the video format doesn't support",
+            ));
+        }
     }
 
     extern "C" fn vblank_callback(gb: *mut GB_gameboy_t, kind: GB_vblank_type_t) {
@@ -89,7 +250,20 @@ impl SameBoy {
         //         while holding a `&mut`. No other references are live here.
         //         Also, the pointer is guaranteed to be non-NULL.
         let this = unsafe { (gb as *mut SameBoy).as_mut().unwrap_unchecked() };
-        this.update_screen();
+        this.last_frame = this.live_pixels();
+        this.render_everything();
+        if this.held_hotkeys.is_rewinding() {
+            this.rewind_pending = true;
+        } else if let Some(recorder) = &mut this.recorder
+            && let Err(err) = recorder.push_frame(&this.framebuffer)
+        {
+            this.abort_recording(err);
+        }
+
+        this.frames_since_battery_save += 1;
+        if this.frames_since_battery_save >= BATTERY_SAVE_INTERVAL {
+            this.save_battery();
+        }
     }
 
     extern "C" fn rgb_encode_callback(_gb: *mut GB_gameboy_t, r: u8, g: u8, b: u8) -> u32 {
@@ -144,10 +318,26 @@ impl SameBoy {
         //         while holding a `&mut`. No other references are live here.
         //         Also, the pointer is guaranteed to be non-NULL.
         let this = unsafe { (gb as *mut SameBoy).as_mut().unwrap_unchecked() };
-        match this.debugger_receiver.recv() {
-            Err(RecvError) => std::ptr::null_mut(),
-            Ok(command) => command.into_raw(),
-        }
+        let state = this.cpu_state();
+        this.frontend.update_cpu_panel(state);
+        this.show_disassembly();
+        this.frontend.debugger_stopped(true);
+        this.halt.set_debugger_stopped(true);
+        let command = loop {
+            let input = match this.deferred_commands.pop_front() {
+                Some(command) => Ok(DebuggerInput::Command(command)),
+                None => this.debugger_receiver.recv(),
+            };
+            match input {
+                Err(RecvError) => break std::ptr::null_mut(),
+                Ok(DebuggerInput::Command(command)) => break command.into_raw(),
+                Ok(DebuggerInput::Request(request)) => request.handle(this),
+            }
+        };
+        this.halt.set_debugger_stopped(false);
+        this.handle_late_requests();
+        this.frontend.debugger_stopped(false);
+        command
     }
 
     extern "C" fn async_input_callback(gb: *mut GB_gameboy_t) -> *mut c_char {
@@ -156,9 +346,33 @@ impl SameBoy {
         //         while holding a `&mut`. No other references are live here.
         //         Also, the pointer is guaranteed to be non-NULL.
         let this = unsafe { (gb as *mut SameBoy).as_mut().unwrap_unchecked() };
-        match this.debugger_receiver.try_recv() {
-            Err(TryRecvError::Disconnected | TryRecvError::Empty) => std::ptr::null_mut(),
-            Ok(command) => command.into_raw(),
+        this.try_recv_debugger_command()
+            .map_or(std::ptr::null_mut(), DebuggerCmdStr::into_raw)
+    }
+
+    extern "C" fn sample_callback(gb: *mut GB_gameboy_t, sample: *mut GB_sample_t) {
+        debug_assert_eq!(std::mem::offset_of!(SameBoy, gb), 0);
+        // SAFETY: This callback is called from one of the `run` functions, which are all called
+        //         while holding a `&mut`. No other references are live here.
+        //         Also, the pointer is guaranteed to be non-NULL.
+        let this = unsafe { (gb as *mut SameBoy).as_mut().unwrap_unchecked() };
+        // SAFETY: SameBoy passes a pointer to a valid sample, which it doesn't touch during the callback.
+        let sample = unsafe { sample.read() };
+
+        // Rewinding plays a few samples of each frame in a jumbled order, which sounds awful.
+        // (The frames aren't recorded either, so this keeps the recording in sync.)
+        if this.held_hotkeys.is_rewinding() {
+            return;
+        }
+        if let Some(sink) = &mut this.audio
+            && !this.muted
+        {
+            sink.push([sample.left, sample.right].map(|amplitude| amplitude as f32 / 32768.0));
+        }
+        if let Some(recorder) = &mut this.recorder
+            && let Err(err) = recorder.push_sample([sample.left, sample.right])
+        {
+            this.abort_recording(err);
         }
     }
 
@@ -176,64 +390,291 @@ impl SameBoy {
         // SAFETY: SameBoy passes a C string, since it's written in C.
         //         (The length cannot be guaranteed, but it's wildly unrealistic.)
         let c_string = unsafe { CStr::from_ptr(string_ptr) };
-        let string = SharedString::from(c_string.to_string_lossy().as_ref());
-
-        let debugger_window = this.debugger_window.clone();
-        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
-        let _ = slint::invoke_from_event_loop(move || {
-            // Do nothing if the debugger window has been destroyed.
-            if let Some(debugger_window) = debugger_window.upgrade() {
-                let log = debugger_window.get_log();
-                let model = log
-                    .as_any()
-                    .downcast_ref::<VecModel<(Brush, SharedString)>>()
-                    .expect("We set this to a VecModel?!?");
-                if let Some((before, after)) = string.split_once('\n') {
-                    let (brush, mut current) = model.row_data(model.row_count() - 1).unwrap();
-                    current.push_str(before);
-                    model.set_row_data(model.row_count() - 1, (brush, current));
-                    for line in after.split('\n') {
-                        model.push((debugger_window.get_out_color(), line.into()));
-                    }
-                } else {
-                    let (brush, mut current) = model.row_data(model.row_count() - 1).unwrap();
-                    current.push_str(&string);
-                    model.set_row_data(model.row_count() - 1, (brush, current));
-                }
-            }
-        });
+        match &mut this.log_capture {
+            Some(capture) => capture.push_str(&c_string.to_string_lossy()),
+            None => this.frontend.log(&c_string.to_string_lossy(), attributes),
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// What the UI tells the emulation thread to do.
 pub enum Schedule {
+    /// Let go of the emulator, and acknowledge it through [`Halt`].
     Stop,
     Run,
     Step,
     RunFrame,
+    /// Changes a setting once the emulator isn't in the middle of running, which it is while the debugger
+    /// waits for a command; emulation keeps going or stays stopped as it was.
+    Configure(Box<dyn FnOnce(&mut SameBoy) + Send>),
 
     Quit,
 }
+
+/// How the UI thread gets hold of the emulator.
+///
+/// After sending a [`Schedule::Stop`], it waits for the emulation thread to acknowledge it, at which point the
+/// emulator is free. However, while the debugger waits for a command, it holds onto the emulator from within
+/// SameBoy's run loop, and the emulation thread can't get to the message; only [`DebuggerRequest`]s can be made then.
+#[derive(Debug, Default)]
+pub struct Halt {
+    state: Mutex<HaltState>,
+    changed: Condvar,
+}
+#[derive(Debug, Default)]
+struct HaltState {
+    stops_acknowledged: u64,
+    debugger_stopped: bool,
+    /// The emulation thread has exited, possibly by panicking, and won't acknowledge anything anymore.
+    ended: bool,
+}
+impl Halt {
+    pub fn is_debugger_stopped(&self) -> bool {
+        self.state.lock().unwrap().debugger_stopped
+    }
+
+    fn set_debugger_stopped(&self, stopped: bool) {
+        self.update(|state| state.debugger_stopped = stopped);
+    }
+
+    /// To be called by the emulation thread when it gets to a [`Schedule::Stop`], once it has let go of the emulator.
+    pub fn acknowledge_stop(&self) {
+        self.update(|state| state.stops_acknowledged += 1);
+    }
+
+    /// To be called when the emulation thread exits.
+    pub fn end(&self) {
+        self.update(|state| state.ended = true);
+    }
+
+    /// Waits until `stops` [`Schedule::Stop`]s have been acknowledged in total, and returns `true`.
+    /// If the debugger holds onto the emulator instead, calls `in_debugger` before it can resume, and returns `false`.
+    pub fn wait(&self, stops: u64, in_debugger: impl FnOnce()) -> bool {
+        let state = self
+            .changed
+            .wait_while(self.state.lock().unwrap(), |state| {
+                state.stops_acknowledged < stops && !state.debugger_stopped && !state.ended
+            })
+            .unwrap();
+        assert!(!state.ended, "The emulation thread has exited");
+        if state.stops_acknowledged >= stops {
+            return true;
+        }
+        in_debugger();
+        false
+    }
+
+    fn update(&self, f: impl FnOnce(&mut HaltState)) {
+        f(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+/// How fast emulation runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    /// Relative to the real console.
+    Multiplier(f64),
+    /// As fast as possible.
+    Unlimited,
+}
+impl Speed {
+    pub const NORMAL: Self = Self::Multiplier(1.0);
+
+    /// 0 means unlimited.
+    pub fn from_percent(percent: u32) -> Self {
+        match percent {
+            0 => Self::Unlimited,
+            percent => Self::Multiplier(f64::from(percent) / 100.0),
+        }
+    }
+}
+
 /// Execution.
 impl SameBoy {
+    /// Forwards any changes to the joypad state to SameBoy.
+    fn sync_joypad(&mut self) {
+        let state = self.joypad.state();
+        if state == self.applied_joypad {
+            return;
+        }
+        for button in Button::ALL {
+            let pressed = state.is_pressed(button);
+            if pressed != self.applied_joypad.is_pressed(button) {
+                let key = match button {
+                    Button::Right => GB_key_t_GB_KEY_RIGHT,
+                    Button::Left => GB_key_t_GB_KEY_LEFT,
+                    Button::Up => GB_key_t_GB_KEY_UP,
+                    Button::Down => GB_key_t_GB_KEY_DOWN,
+                    Button::A => GB_key_t_GB_KEY_A,
+                    Button::B => GB_key_t_GB_KEY_B,
+                    Button::Select => GB_key_t_GB_KEY_SELECT,
+                    Button::Start => GB_key_t_GB_KEY_START,
+                };
+                // SAFETY: `gb` is initialised, and not running (we couldn't have a mutable ref to it otherwise).
+                unsafe { GB_set_key_state(&mut self.gb, key, pressed) };
+            }
+        }
+        self.applied_joypad = state;
+    }
+
+    /// Forwards any changes to the speed to SameBoy, including from the fast-forward hotkey.
+    fn sync_speed(&mut self) {
+        let speed = if self.held_hotkeys.is_fast_forwarding() {
+            Speed::Unlimited
+        } else {
+            self.speed
+        };
+        let recording_video = self.is_recording();
+        let applied = (speed, recording_video, self.recording_audio);
+        if self.applied_speed == Some(applied) {
+            return;
+        }
+
+        let (multiplier, turbo) = match speed {
+            // Changing the clock rate changes how many samples are produced per frame, which would throw off
+            // recordings; turbo mode doesn't. The UI shows the speed as 100% meanwhile.
+            Speed::Multiplier(_) if recording_video || self.recording_audio => (1.0, false),
+            Speed::Multiplier(multiplier) => (multiplier, false),
+            Speed::Unlimited => (1.0, true),
+        };
+        // SAFETY: `gb` is initialised, and not running (we couldn't have a mutable ref to it otherwise).
+        unsafe {
+            GB_set_clock_multiplier(&mut self.gb, multiplier);
+            // Skipped frames would be missing from the recording.
+            GB_set_turbo_mode(&mut self.gb, turbo, recording_video);
+        }
+        // A different clock rate changes the pitch, and turbo mode produces samples faster than they can be played.
+        self.muted = turbo || multiplier != 1.0;
+        self.applied_speed = Some(applied);
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
     pub fn run_once(&mut self) {
+        if std::mem::take(&mut self.rewind_pending) {
+            // The frame that was just completed has been pushed to the rewind buffer; popping it and the one
+            // before that, and then running forward to the next VBlank, displays the previous frame.
+            self.rewind_pop();
+            self.rewind_pop();
+            return;
+        }
+        self.sync_joypad();
+        self.sync_speed();
         // SAFETY: `gb` is initialised, and not running (we couldn't have a mutable ref to it otherwise).
         unsafe { GB_run(&mut self.gb) };
     }
 
     pub fn step(&mut self) {
+        self.sync_joypad();
         // SAFETY: `gb` is initialised, and not running (we couldn't have a mutable ref to it otherwise).
         unsafe {
             GB_set_turbo_mode(&mut self.gb, true, true); // Disable timekeeping.
             let _ = GB_run(&mut self.gb);
-            GB_set_turbo_mode(&mut self.gb, false, true);
         }
+        // Have `run_once` restore the turbo mode.
+        self.applied_speed = None;
     }
 
     pub fn run_frame(&mut self) {
+        self.sync_joypad();
         // SAFETY: `gb` is initialised, and not running (we couldn't have a mutable ref to it otherwise).
         unsafe { GB_run_frame(&mut self.gb) };
     }
+
+    /// Runs for at least the given number of clock cycles, counted at 4 MiHz even in CGB double-speed mode.
+    /// Returns how many cycles were actually run.
+    pub fn run_cycles(&mut self, cycles: u64) -> u64 {
+        self.sync_joypad();
+        // `GB_run` reports how much time passed in 8 MiHz ticks.
+        let mut ticks = 0;
+        while ticks < cycles * 2 {
+            // SAFETY: `gb` is initialised, and not running (we couldn't have a mutable ref to it otherwise).
+            ticks += u64::from(unsafe { GB_run(&mut self.gb) });
+        }
+        ticks / 2
+    }
+
+    /// Makes the emulator run as fast as it can instead of in real time, without skipping any frames.
+    pub fn disable_timekeeping(&mut self) {
+        // SAFETY: `gb` is initialised, and not running (we couldn't have a mutable ref to it otherwise).
+        unsafe { GB_set_turbo_mode(&mut self.gb, true, true) };
+    }
+}
+
+/// Rewinding.
+impl SameBoy {
+    /// Sets how far back it's possible to rewind; 0 disables rewinding altogether.
+    pub fn set_rewind_length(&mut self, seconds: f64) {
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        unsafe { GB_set_rewind_length(&mut self.gb, seconds) };
+    }
+
+    /// Restores the last state pushed to the rewind buffer; returns `false` if it was empty.
+    pub fn rewind_pop(&mut self) -> bool {
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        unsafe { GB_rewind_pop(&mut self.gb) }
+    }
+}
+
+/// The hardware models that can be emulated, each with a stable identifier and a human-readable name.
+pub const MODELS: [(GB_model_t, &str, &str); 13] = [
+    (GB_model_t_GB_MODEL_DMG_B, "dmg-b", "DMG-B"),
+    (GB_model_t_GB_MODEL_MGB, "mgb", "MGB"),
+    (GB_model_t_GB_MODEL_SGB_NTSC, "sgb-ntsc", "SGB (NTSC)"),
+    (GB_model_t_GB_MODEL_SGB_PAL, "sgb-pal", "SGB (PAL)"),
+    (GB_model_t_GB_MODEL_SGB2, "sgb2", "SGB2"),
+    (GB_model_t_GB_MODEL_CGB_0, "cgb-0", "CGB-0"),
+    (GB_model_t_GB_MODEL_CGB_A, "cgb-a", "CGB-A"),
+    (GB_model_t_GB_MODEL_CGB_B, "cgb-b", "CGB-B"),
+    (GB_model_t_GB_MODEL_CGB_C, "cgb-c", "CGB-C"),
+    (GB_model_t_GB_MODEL_CGB_D, "cgb-d", "CGB-D"),
+    (GB_model_t_GB_MODEL_CGB_E, "cgb-e", "CGB-E"),
+    (GB_model_t_GB_MODEL_AGB_A, "agb", "AGB"),
+    (GB_model_t_GB_MODEL_GBP_A, "gbp", "GBP"),
+];
+
+/// Which model to emulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelChoice {
+    /// Pick DMG, CGB or SGB depending on what the ROM's header says it supports.
+    Auto,
+    Fixed(GB_model_t),
+}
+impl ModelChoice {
+    /// A stable identifier, used for persisting the choice.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Fixed(model) => MODELS
+                .iter()
+                .find(|(candidate, ..)| *candidate == model)
+                .map_or("auto", |(_, name, _)| name),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name == "auto" {
+            return Some(Self::Auto);
+        }
+        MODELS
+            .iter()
+            .find(|(_, candidate, _)| *candidate == name)
+            .map(|(model, ..)| Self::Fixed(*model))
+    }
+
+    /// A human-readable name.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Auto => "Auto",
+            Self::Fixed(model) => MODELS
+                .iter()
+                .find(|(candidate, ..)| *candidate == model)
+                .map_or("?", |(.., label)| label),
+        }
+    }
 }
 
 /// Resetting.
@@ -244,49 +685,1410 @@ impl SameBoy {
         self.resize_framebuffer();
     }
 
-    pub fn load_rom(&mut self, path: &Path) {
-        use std::ffi::CString;
-        #[cfg(unix)]
-        fn convert_path(path: &Path) -> Option<CString> {
-            use std::os::unix::ffi::OsStrExt;
-            CString::new(path.as_os_str().as_bytes()).ok()
+    /// Changes which model is emulated, and resets the console.
+    pub fn set_model(&mut self, choice: ModelChoice) {
+        self.model_choice = choice;
+        let model = self.chosen_model();
+        self.change_model(model);
+    }
+
+    /// Returns the model to emulate, according to [`Self::model_choice`] and the ROM's header.
+    fn chosen_model(&mut self) -> GB_model_t {
+        const CGB_FLAG: usize = 0x143;
+        const SGB_FLAG: usize = 0x146;
+        const OLD_LICENSEE: usize = 0x14B;
+
+        match self.model_choice {
+            ModelChoice::Fixed(model) => model,
+            ModelChoice::Auto => {
+                if self.rom_path.is_none() {
+                    return GB_model_t_GB_MODEL_DMG_B;
+                }
+                let Some(header) = self
+                    .direct_access(GB_direct_access_t_GB_DIRECT_ACCESS_ROM)
+                    .filter(|rom| rom.len() >= 0x150)
+                else {
+                    return GB_model_t_GB_MODEL_DMG_B;
+                };
+                if header[CGB_FLAG] & 0x80 != 0 {
+                    GB_model_t_GB_MODEL_CGB_E
+                // The SGB BIOS ignores the SGB flag unless the old licensee code is $33.
+                } else if header[SGB_FLAG] == 0x03 && header[OLD_LICENSEE] == 0x33 {
+                    GB_model_t_GB_MODEL_SGB_NTSC
+                } else {
+                    GB_model_t_GB_MODEL_DMG_B
+                }
+            }
         }
-        #[cfg(not(unix))]
-        fn convert_path(path: &Path) -> CString {
-            CString::new(path.to_str()?).ok() // Laziness on my behalf? Certainly.
+    }
+
+    /// Loads a ROM and resets the console. If this fails, the previously loaded ROM keeps running.
+    pub fn load_rom(&mut self, path: &Path) -> Result<(), LoadRomError> {
+        /// Anything smaller can't even contain a cartridge header.
+        const MIN_ROM_SIZE: u64 = 0x150;
+
+        let c_path =
+            path_to_c_string(path).ok_or_else(|| LoadRomError::BadPath(path.to_owned()))?;
+        // SameBoy unloads the current ROM as soon as it opens the new file, so check as much as we can beforehand.
+        let metadata = std::fs::File::open(path)
+            .and_then(|file| file.metadata())
+            .map_err(LoadRomError::Unreadable)?;
+        if !metadata.is_file() || metadata.len() < MIN_ROM_SIZE {
+            return Err(LoadRomError::NotARom);
         }
-        match convert_path(path) {
-            Some(c_path) => {
-                // SAFETY: Initialisation is done and all necessary callbacks are set in `new`.
-                let err_code = unsafe { GB_load_rom(&mut self.gb, c_path.as_ptr()) };
+
+        // Don't lose the previous game's progress!
+        self.save_battery();
+        // Keep a way to bring back the previous game if SameBoy fails to load the new one anyway.
+        let previous = self
+            .rom_path
+            .clone()
+            .map(|rom_path| (rom_path, self.save_state()));
+
+        // SAFETY: Initialisation is done and all necessary callbacks are set in `new`.
+        let err_code = unsafe { GB_load_rom(&mut self.gb, c_path.as_ptr()) };
+        if err_code != 0 {
+            if let Some((rom_path, state)) = previous
+                && let Some(c_path) = path_to_c_string(&rom_path)
+            {
+                // SAFETY: ditto.
+                unsafe { GB_load_rom(&mut self.gb, c_path.as_ptr()) };
+                self.load_state(&state);
             }
-            None => todo!(), // Report error
+            return Err(LoadRomError::Rejected(std::io::Error::from_raw_os_error(
+                err_code,
+            )));
+        }
+
+        if self.rom_path.as_deref() != Some(path) {
+            self.symbol_file = None;
         }
+        self.rom_path = Some(path.to_owned());
+        // Labels may have moved if the ROM was rebuilt.
+        self.reload_symbols();
+        // A recording of one game's music shouldn't run into another's; and if the file can't be finalised,
+        // there's nothing more we could do about it than the user can.
+        let _ = self.stop_audio_recording();
+        // Start the new game from a clean slate, on whichever model suits it.
+        let model = self.chosen_model();
+        self.change_model(model);
+        self.load_battery();
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadRomError {
+    /// The path cannot be passed to SameBoy.
+    BadPath(PathBuf),
+    /// The file cannot be read.
+    Unreadable(std::io::Error),
+    /// The file is too small to be a ROM.
+    NotARom,
+    /// SameBoy failed to load the file.
+    Rejected(std::io::Error),
+}
+impl Display for LoadRomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadPath(path) => write!(f, "Unsupported file path: {}", path.display()),
+            Self::Unreadable(err) => write!(f, "Unable to read the file: {err}"),
+            Self::NotARom => write!(f, "This file is too small to be a Game Boy ROM."),
+            Self::Rejected(err) => write!(f, "The emulator was unable to load the file: {err}"),
+        }
+    }
+}
+impl std::error::Error for LoadRomError {}
+
+/// Debugger.
+impl SameBoy {
+    /// Returns how the UI thread can tell whether it can get hold of the emulator.
+    pub fn halt(&self) -> Arc<Halt> {
+        Arc::clone(&self.halt)
+    }
+
+    /// Returns the next command sent to the debugger, if any, answering requests along the way.
+    fn try_recv_debugger_command(&mut self) -> Option<DebuggerCmdStr> {
+        if let Some(command) = self.deferred_commands.pop_front() {
+            return Some(command);
+        }
+        loop {
+            match self.debugger_receiver.try_recv() {
+                Err(TryRecvError::Disconnected | TryRecvError::Empty) => break None,
+                Ok(DebuggerInput::Command(command)) => break Some(command),
+                // Requests are only sent while the debugger is stopped, but someone may be waiting for a reply.
+                Ok(DebuggerInput::Request(request)) => request.handle(self),
+            }
+        }
+    }
+
+    /// Answers the requests that were sent just before the debugger resumed, as their senders may be waiting for a
+    /// reply; commands received in the meantime are kept for later.
+    fn handle_late_requests(&mut self) {
+        while let Ok(input) = self.debugger_receiver.try_recv() {
+            match input {
+                DebuggerInput::Command(command) => self.deferred_commands.push_back(command),
+                DebuggerInput::Request(request) => request.handle(self),
+            }
+        }
+    }
+
+    /// Handles what was sent to the debugger while emulation wasn't running, so it doesn't wait until then.
+    pub fn handle_debugger_input(&mut self) {
+        while let Some(command) = self.try_recv_debugger_command() {
+            // The null command only serves to resume emulation.
+            if !command.0.is_null() {
+                // SAFETY: the instance is initialised, and not running thanks to the mutable reference;
+                //         the command is a valid C string, which SameBoy may modify but doesn't free.
+                unsafe { GB_debugger_execute_command(&mut self.gb, command.0) };
+            }
+        }
+    }
+
+    /// Executes a debugger command right away, as if it had been typed in.
+    pub fn execute_debugger_command(&mut self, command: &str) {
+        let command = DebuggerCmdStr::new(command);
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference;
+        //         the command is a valid C string, which SameBoy may modify but doesn't free.
+        unsafe { GB_debugger_execute_command(&mut self.gb, command.0) };
+    }
+
+    /// Evaluates a debugger expression (such as `$150`, `hl + 2`, or a symbol) to an address, without printing
+    /// anything to the log.
+    pub fn evaluate(&mut self, expression: &str) -> Option<u16> {
+        let expression = CString::new(expression).ok()?;
+        let mut value = 0;
+        // Errors are reported through the log, but the caller decides what to tell the user.
+        self.log_capture = Some(String::new());
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`);
+        //         the expression is a valid C string, and the bank may be null.
+        let error = unsafe {
+            GB_debugger_evaluate(
+                &mut self.gb,
+                expression.as_ptr(),
+                &mut value,
+                std::ptr::null_mut(),
+            )
+        };
+        self.log_capture = None;
+        (!error).then_some(value)
+    }
+}
+
+/// Symbols.
+impl SameBoy {
+    /// Returns the ways the last word of a partially typed debugger command can be completed (command names,
+    /// register names, symbols...), as what must be appended to it.
+    pub fn debugger_completions(&mut self, input: &str) -> Vec<String> {
+        /// In case SameBoy keeps coming up with completions.
+        const MAX_COMPLETIONS: usize = 256;
+
+        let Ok(input) = CString::new(input) else {
+            return vec![];
+        };
+        let mut context = 0;
+        let mut completions = vec![];
+        while completions.len() < MAX_COMPLETIONS {
+            // SameBoy modifies the string that it's given.
+            let mut buffer = input.clone().into_bytes_with_nul();
+            // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`);
+            //         the buffer is a valid C string, and SameBoy doesn't keep it around.
+            let completion = unsafe {
+                GB_debugger_complete_substring(
+                    &mut self.gb,
+                    buffer.as_mut_ptr().cast(),
+                    &mut context,
+                )
+            };
+            if completion.is_null() {
+                break;
+            }
+            // SAFETY: SameBoy returns a valid C string.
+            let suffix = unsafe { CStr::from_ptr(completion) };
+            completions.push(suffix.to_string_lossy().into_owned());
+            // SAFETY: the string was allocated by SameBoy using `malloc`, and we are responsible for freeing it.
+            unsafe { free(completion.cast()) };
+        }
+        completions
+    }
+
+    /// Loads a symbol file on top of the ROM's own, and keeps reloading it along with the ROM.
+    pub fn load_symbol_file(&mut self, path: &Path) -> io::Result<()> {
+        // SameBoy silently ignores files it can't open, so check that beforehand.
+        std::fs::File::open(path)?;
+        path_to_c_string(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported file path"))?;
+        self.symbol_file = Some(path.to_owned());
+        self.reload_symbols();
+        Ok(())
+    }
+
+    /// Replaces the debugger's symbols with the ROM's `.sym` file (as generated by RGBDS) if there is one,
+    /// and the symbol file loaded by hand if there is one.
+    fn reload_symbols(&mut self) {
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        unsafe { GB_debugger_clear_symbols(&mut self.gb) };
+        let sibling = self
+            .rom_path
+            .as_ref()
+            .map(|rom_path| rom_path.with_extension("sym"))
+            .filter(|path| path.is_file());
+        let by_hand = self
+            .symbol_file
+            .as_ref()
+            .filter(|&path| Some(path) != sibling.as_ref());
+        for path in sibling.iter().chain(by_hand) {
+            if let Some(c_path) = path_to_c_string(path) {
+                // SAFETY: the instance is initialised, and not running thanks to the mutable reference;
+                //         SameBoy doesn't keep the path around.
+                unsafe { GB_debugger_load_symbol_file(&mut self.gb, c_path.as_ptr()) };
+            }
+        }
+    }
+}
+
+/// Memory access.
+impl SameBoy {
+    /// Returns one of SameBoy's internal memory buffers, or `None` if it doesn't exist (e.g. no cartridge RAM).
+    fn direct_access(&mut self, access: GB_direct_access_t) -> Option<&mut [u8]> {
+        self.direct_access_banked(access).map(|(buffer, _)| buffer)
+    }
+
+    /// Like [`Self::direct_access`], but also returns which of the buffer's banks is currently mapped.
+    fn direct_access_banked(&mut self, access: GB_direct_access_t) -> Option<(&mut [u8], u16)> {
+        let mut size = 0;
+        let mut bank = 0;
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`).
+        let ptr =
+            unsafe { GB_get_direct_access(&mut self.gb, access, &mut size, &mut bank) } as *mut u8;
+        if ptr.is_null() || size == 0 {
+            return None;
+        }
+        // SAFETY: SameBoy guarantees that the buffer is `size` bytes long, and it won't be touched by anything
+        //         else while we hold a mutable reference to the instance.
+        Some((unsafe { std::slice::from_raw_parts_mut(ptr, size) }, bank))
+    }
+
+    /// Reads a byte from the memory bus, as the CPU would, but without side effects.
+    pub fn read_memory(&mut self, addr: u16) -> u8 {
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`).
+        unsafe { GB_safe_read_memory(&mut self.gb, addr) }
+    }
+
+    /// Writes a byte to a memory region.
+    /// On the bus, this is a CPU write, with all of its side effects (e.g. switching banks when "writing" to ROM);
+    /// anywhere else, the byte is written directly, so that e.g. ROM can be patched.
+    /// Returns `false` if the address doesn't exist in that region.
+    pub fn write_memory(&mut self, region: MemoryRegion, bank: u16, addr: u16, value: u8) -> bool {
+        let Some(access) = region.direct_access() else {
+            // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`).
+            unsafe { GB_write_memory(&mut self.gb, addr, value) };
+            return true;
+        };
+        let (bank_size, base) = region.bank_layout(bank);
+        let Some(offset) = addr.checked_sub(base).map(usize::from) else {
+            return false;
+        };
+        let index = usize::from(bank) * bank_size + offset;
+        match self.direct_access(access) {
+            Some(buffer) if offset < bank_size && index < buffer.len() => {
+                buffer[index] = value;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the name of the symbol at an address, as currently mapped, if a symbol file defines one.
+    pub fn symbol_at(&mut self, addr: u16) -> Option<String> {
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`).
+        let name = unsafe { GB_debugger_name_for_address(&mut self.gb, addr) };
+        if name.is_null() {
+            return None;
+        }
+        // SAFETY: SameBoy returns a valid C string, which lives at least until the symbols change.
+        let name = unsafe { CStr::from_ptr(name) };
+        Some(name.to_string_lossy().into_owned())
+    }
+}
+
+/// A part of the address space that the memory viewer can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegion {
+    /// The whole address space, as the CPU sees it.
+    Bus,
+    Rom,
+    Vram,
+    Wram,
+    Sram,
+    Oam,
+    Io,
+    Hram,
+}
+
+impl MemoryRegion {
+    /// Same order as in the memory viewer's combo box.
+    pub const ALL: [Self; 8] = [
+        Self::Bus,
+        Self::Rom,
+        Self::Vram,
+        Self::Wram,
+        Self::Sram,
+        Self::Oam,
+        Self::Io,
+        Self::Hram,
+    ];
+
+    fn direct_access(self) -> Option<GB_direct_access_t> {
+        match self {
+            Self::Bus => None,
+            Self::Rom => Some(GB_direct_access_t_GB_DIRECT_ACCESS_ROM),
+            Self::Vram => Some(GB_direct_access_t_GB_DIRECT_ACCESS_VRAM),
+            Self::Wram => Some(GB_direct_access_t_GB_DIRECT_ACCESS_RAM),
+            Self::Sram => Some(GB_direct_access_t_GB_DIRECT_ACCESS_CART_RAM),
+            Self::Oam => Some(GB_direct_access_t_GB_DIRECT_ACCESS_OAM),
+            Self::Io => Some(GB_direct_access_t_GB_DIRECT_ACCESS_IO),
+            Self::Hram => Some(GB_direct_access_t_GB_DIRECT_ACCESS_HRAM),
+        }
+    }
+
+    /// Returns the size of the region's banks, and the address at which the given bank is mapped.
+    fn bank_layout(self, bank: u16) -> (usize, u16) {
+        match self {
+            Self::Bus => (0x10000, 0x0000),
+            // Bank 0 is always mapped at $0000, and the others are switched in at $4000.
+            Self::Rom => (0x4000, if bank == 0 { 0x0000 } else { 0x4000 }),
+            Self::Vram => (0x2000, 0x8000),
+            // Likewise, bank 0 is always mapped at $C000, and the others are switched in at $D000.
+            Self::Wram => (0x1000, if bank == 0 { 0xC000 } else { 0xD000 }),
+            Self::Sram => (0x2000, 0xA000),
+            Self::Oam => (0xA0, 0xFE00),
+            Self::Io => (0x80, 0xFF00),
+            Self::Hram => (0x7F, 0xFF80),
+        }
+    }
+
+    /// The addresses at which a bank of the region is mapped.
+    pub fn address_range(self, bank: u16) -> RangeInclusive<u16> {
+        let (size, base) = self.bank_layout(bank);
+        base..=base + (size - 1) as u16
+    }
+}
+
+/// A copy of the CPU's registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl Registers {
+    pub fn get(&self, register: Register) -> u16 {
+        match register {
+            Register::Af => self.af,
+            Register::Bc => self.bc,
+            Register::De => self.de,
+            Register::Hl => self.hl,
+            Register::Sp => self.sp,
+            Register::Pc => self.pc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    Af,
+    Bc,
+    De,
+    Hl,
+    Sp,
+    Pc,
+}
+
+impl Register {
+    /// Same order as in SameBoy's `GB_registers_t`.
+    pub const ALL: [Self; 6] = [Self::Af, Self::Bc, Self::De, Self::Hl, Self::Sp, Self::Pc];
+
+    /// The register's name, as understood by SameBoy's debugger.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Af => "af",
+            Self::Bc => "bc",
+            Self::De => "de",
+            Self::Hl => "hl",
+            Self::Sp => "sp",
+            Self::Pc => "pc",
+        }
+    }
+}
+
+/// What the debugger's CPU panel shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    pub registers: Registers,
+    pub ime: bool,
+    pub halted: bool,
+    /// The ROM bank mapped at $4000-7FFF.
+    pub rom_bank: u16,
+    /// The cartridge RAM bank mapped at $A000-BFFF, if there is cartridge RAM.
+    pub sram_bank: Option<u16>,
+    /// The WRAM bank mapped at $D000-DFFF; only the CGB can switch it.
+    pub wram_bank: u16,
+    /// Only the CGB can switch it.
+    pub vram_bank: u16,
+}
+
+/// A line of disassembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassemblyLine {
+    /// The instruction's address, or `None` if the line is a label.
+    pub addr: Option<u16>,
+    pub text: String,
+    /// Whether the instruction is at PC.
+    pub current: bool,
+}
+
+/// How many instructions are shown when the debugger stops.
+const DISASSEMBLY_LENGTH: u16 = 64;
+
+/// CPU state.
+impl SameBoy {
+    pub fn registers(&mut self) -> Registers {
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`);
+        //         the pointer points into the instance, so it's valid.
+        //         All of the union's fields cover the same plain integers, so any of them can be read.
+        let registers = unsafe { (*GB_get_registers(&mut self.gb)).__bindgen_anon_1 };
+        Registers {
+            af: registers.af,
+            bc: registers.bc,
+            de: registers.de,
+            hl: registers.hl,
+            sp: registers.sp,
+            pc: registers.pc,
+        }
+    }
+
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        // The low 4 bits of F don't exist.
+        let value = if register == Register::Af {
+            value & 0xFFF0
+        } else {
+            value
+        };
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`);
+        //         the pointer points into the instance, so it's valid.
+        //         All of the union's fields cover the same plain integers, so any of them can be written.
+        unsafe { (*GB_get_registers(&mut self.gb)).registers[register as usize] = value };
+    }
+
+    /// Shows the code starting at PC in the debugger.
+    fn show_disassembly(&mut self) {
+        let pc = self.registers().pc;
+        let disassembly = self.disassemble(pc, DISASSEMBLY_LENGTH);
+        self.frontend.update_disassembly(disassembly);
+    }
+
+    /// Disassembles `count` instructions starting at `addr`, with labels from the symbol file if one is loaded.
+    pub fn disassemble(&mut self, addr: u16, count: u16) -> Vec<DisassemblyLine> {
+        self.log_capture = Some(String::new());
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`).
+        unsafe { GB_cpu_disassemble(&mut self.gb, addr, count) };
+        let output = self.log_capture.take().unwrap_or_default();
+
+        // Instructions look like `  ->0150 <+003>: ld a, b`, where `->` marks PC, and the offset from the last label
+        // is only there if there is one. Labels are on their own line, like `Main:`.
+        output
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let instruction = line.get(4..).and_then(|rest| {
+                    let (location, text) = rest.split_once(": ")?;
+                    let addr = u16::from_str_radix(location.get(..4)?, 16).ok()?;
+                    Some((addr, location[4..].trim(), text))
+                });
+                match instruction {
+                    Some((addr, offset, text)) => DisassemblyLine {
+                        addr: Some(addr),
+                        text: format!("{addr:04X} {offset:7} {text}"),
+                        current: line.starts_with("  ->"),
+                    },
+                    None => DisassemblyLine {
+                        addr: None,
+                        text: line.to_owned(),
+                        current: false,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    pub fn cpu_state(&mut self) -> CpuState {
+        let mut bank = |access| self.direct_access_banked(access).map(|(_, bank)| bank);
+        let rom_bank = bank(GB_direct_access_t_GB_DIRECT_ACCESS_ROM).unwrap_or(1);
+        let sram_bank = bank(GB_direct_access_t_GB_DIRECT_ACCESS_CART_RAM);
+        let wram_bank = bank(GB_direct_access_t_GB_DIRECT_ACCESS_RAM).unwrap_or(1);
+        let vram_bank = bank(GB_direct_access_t_GB_DIRECT_ACCESS_VRAM).unwrap_or(0);
+        CpuState {
+            registers: self.registers(),
+            // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`).
+            ime: unsafe { aythya_get_ime(&mut self.gb) },
+            // SAFETY: ditto.
+            halted: unsafe { aythya_is_halted(&mut self.gb) },
+            rom_bank,
+            sram_bank,
+            wram_bank,
+            vram_bank,
+        }
+    }
+}
+
+/// Test ROMs.
+impl SameBoy {
+    /// Starts watching for test ROMs reporting their results; see [`SameBoy::test_monitor`].
+    pub fn monitor_tests(&mut self) {
+        self.test_monitor = Some(TestMonitor::default());
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        unsafe { GB_set_execution_callback(&mut self.gb, Some(Self::execution_callback)) };
+        // SAFETY: ditto.
+        unsafe {
+            GB_set_serial_transfer_bit_start_callback(
+                &mut self.gb,
+                Some(Self::serial_bit_start_callback),
+            )
+        };
+        // SAFETY: ditto.
+        unsafe {
+            GB_set_serial_transfer_bit_end_callback(
+                &mut self.gb,
+                Some(Self::serial_bit_end_callback),
+            )
+        };
+    }
+
+    /// Returns the test monitor, after checking for results; `None` if not monitoring tests.
+    /// This should be called regularly, as it's also how the monitor notices that serial output has stopped.
+    pub fn test_monitor(&mut self) -> Option<&TestMonitor> {
+        let mut monitor = self.test_monitor.take()?;
+        monitor.check_serial();
+        monitor.check_memory(|addr| self.read_memory(addr));
+        Some(self.test_monitor.insert(monitor))
+    }
+
+    extern "C" fn execution_callback(gb: *mut GB_gameboy_t, _address: u16, opcode: u8) {
+        if opcode != testrom::MOONEYE_BREAKPOINT {
+            return;
+        }
+        debug_assert_eq!(std::mem::offset_of!(SameBoy, gb), 0);
+        // SAFETY: This callback is called from one of the `run` functions, which are all called
+        //         while holding a `&mut`. No other references are live here.
+        //         Also, the pointer is guaranteed to be non-NULL.
+        let this = unsafe { (gb as *mut SameBoy).as_mut().unwrap_unchecked() };
+        let Registers { bc, de, hl, .. } = this.registers();
+        let [b, c] = bc.to_be_bytes();
+        let [d, e] = de.to_be_bytes();
+        let [h, l] = hl.to_be_bytes();
+        if let Some(monitor) = &mut this.test_monitor {
+            monitor.mooneye_breakpoint([b, c, d, e, h, l]);
+        }
+    }
+
+    extern "C" fn serial_bit_start_callback(gb: *mut GB_gameboy_t, bit_to_send: bool) {
+        debug_assert_eq!(std::mem::offset_of!(SameBoy, gb), 0);
+        // SAFETY: This callback is called from one of the `run` functions, which are all called
+        //         while holding a `&mut`. No other references are live here.
+        //         Also, the pointer is guaranteed to be non-NULL.
+        let this = unsafe { (gb as *mut SameBoy).as_mut().unwrap_unchecked() };
+        if let Some(monitor) = &mut this.test_monitor {
+            monitor.serial_bit(bit_to_send);
+        }
+    }
+
+    extern "C" fn serial_bit_end_callback(_gb: *mut GB_gameboy_t) -> bool {
+        true // Nothing is connected, so the line stays high.
+    }
+}
+
+/// Identifies a ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    /// The title from the ROM's header.
+    pub title: String,
+    /// CRC32 of the whole ROM, to tell apart different versions of a same game.
+    pub crc32: u32,
+}
+
+/// ROM information.
+impl SameBoy {
+    /// Path to the currently loaded ROM, if any.
+    pub fn rom_path(&self) -> Option<&Path> {
+        self.rom_path.as_deref()
+    }
+
+    /// Returns information about the currently loaded ROM, or `None` if no ROM is loaded.
+    pub fn rom_info(&mut self) -> Option<RomInfo> {
+        self.rom_path.as_ref()?;
+        let mut title = [0; 17];
+        // SAFETY: the instance is initialised, and the buffer is as large as SameBoy requires.
+        unsafe { GB_get_rom_title(&mut self.gb, title.as_mut_ptr()) };
+        // SAFETY: SameBoy NUL-terminates the title.
+        let title = unsafe { CStr::from_ptr(title.as_ptr()) };
+        Some(RomInfo {
+            title: title.to_string_lossy().into_owned(),
+            // SAFETY: the instance is initialised.
+            crc32: unsafe { GB_get_rom_crc32(&mut self.gb) },
+        })
+    }
+}
+
+/// Save states.
+impl SameBoy {
+    pub fn save_state(&mut self) -> Vec<u8> {
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        let size = unsafe { GB_get_save_state_size(&mut self.gb) };
+        let mut state = vec![0; size];
+        // SAFETY: ditto; and the buffer is exactly as large as SameBoy asked for.
+        unsafe { GB_save_state_to_buffer(&mut self.gb, state.as_mut_ptr()) };
+        state
+    }
+
+    /// Returns whether the state could be loaded; if not, the emulator is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> bool {
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        let err_code =
+            unsafe { GB_load_state_from_buffer(&mut self.gb, state.as_ptr(), state.len()) };
+        // The state may have been saved on a model with a different screen size (e.g. SGB).
+        self.resize_framebuffer();
+        err_code == 0
+    }
+}
+
+/// Battery saves.
+impl SameBoy {
+    /// Sets the directory where battery saves are stored; if `None`, they are stored next to the ROM.
+    ///
+    /// This takes effect the next time the battery save is written.
+    pub fn set_save_dir(&mut self, save_dir: Option<PathBuf>) {
+        self.save_dir = save_dir;
+    }
+
+    /// Sets whether battery saves are used at all; this takes effect the next time a ROM is loaded.
+    pub fn set_battery_saves(&mut self, enabled: bool) {
+        self.battery_saves = enabled;
+    }
+
+    /// Returns the path that the current ROM's battery save should be written to, if any.
+    fn battery_save_path(&self) -> Option<PathBuf> {
+        if !self.battery_saves {
+            return None;
+        }
+        let rom_path = self.rom_path.as_ref()?;
+        let save_path = rom_path.with_extension("sav");
+        Some(match &self.save_dir {
+            Some(dir) => dir.join(save_path.file_name()?),
+            None => save_path,
+        })
+    }
+
+    fn load_battery(&mut self) {
+        self.last_battery_save.clear();
+        self.frames_since_battery_save = 0;
+        let Some(mut save_path) = self.battery_save_path() else {
+            return;
+        };
+        // Fall back to a save next to the ROM, which is also what other emulators do by default.
+        if !save_path.exists() {
+            save_path = self.rom_path.as_ref().unwrap().with_extension("sav");
+            if !save_path.exists() {
+                return;
+            }
+        }
+        let Some(c_path) = path_to_c_string(&save_path) else {
+            eprintln!("Unable to load battery save from {}", save_path.display());
+            return;
+        };
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        let err_code = unsafe { GB_load_battery(&mut self.gb, c_path.as_ptr()) };
+        if err_code != 0 {
+            eprintln!(
+                "Unable to load battery save from {}: {}",
+                save_path.display(),
+                std::io::Error::from_raw_os_error(err_code),
+            );
+            return;
+        }
+        self.last_battery_save = self.battery_contents();
+    }
+
+    fn battery_contents(&mut self) -> Vec<u8> {
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        let size = unsafe { GB_save_battery_size(&mut self.gb) };
+        let mut contents = vec![0; size.try_into().unwrap_or(0)];
+        // SAFETY: ditto; and the buffer is exactly as large as SameBoy asked for.
+        unsafe { GB_save_battery_to_buffer(&mut self.gb, contents.as_mut_ptr(), contents.len()) };
+        contents
+    }
+
+    /// Writes the battery save to disk, if the cartridge has a battery and its contents changed.
+    pub fn save_battery(&mut self) {
+        self.frames_since_battery_save = 0;
+        let Some(save_path) = self.battery_save_path() else {
+            return;
+        };
+        let contents = self.battery_contents();
+        if contents.is_empty() || contents == self.last_battery_save {
+            return;
+        }
+        match write_atomically(&save_path, &contents) {
+            Ok(()) => self.last_battery_save = contents,
+            Err(err) => eprintln!(
+                "Unable to write battery save to {}: {err}",
+                save_path.display()
+            ),
+        }
+    }
+}
+
+/// Writes a file such that either the old or new contents are visible, even if we crash halfway through.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp_path, path)
+}
+
+#[cfg(unix)]
+fn path_to_c_string(path: &Path) -> Option<CString> {
+    use std::os::unix::ffi::OsStrExt;
+    CString::new(path.as_os_str().as_bytes()).ok()
+}
+#[cfg(not(unix))]
+fn path_to_c_string(path: &Path) -> Option<CString> {
+    CString::new(path.to_str()?).ok() // Laziness on my behalf? Certainly.
+}
+
+/// Recording.
+impl SameBoy {
+    /// Starts recording video to the given path, and audio alongside it; see [`Recorder::start`].
+    pub fn start_recording(&mut self, video_path: &Path) -> io::Result<()> {
+        self.stop_recording()?;
+        let (width, height) = self.screen_size();
+        self.recorder = Some(Recorder::start(
+            video_path,
+            width,
+            height,
+            self.sample_rate,
+        )?);
+        Ok(())
+    }
+
+    /// Stops recording, if recording.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        self.recorder.take().map_or(Ok(()), Recorder::finish)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Starts recording audio to a WAV file, using SameBoy's recorder.
+    pub fn start_audio_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_audio_recording()?;
+        let c_path = path_to_c_string(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported file path"))?;
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference;
+        //         SameBoy doesn't keep the path around.
+        let err_code = unsafe {
+            GB_start_audio_recording(
+                &mut self.gb,
+                c_path.as_ptr(),
+                GB_audio_format_t_GB_AUDIO_FORMAT_WAV,
+            )
+        };
+        if err_code != 0 {
+            return Err(io::Error::from_raw_os_error(err_code));
+        }
+        self.recording_audio = true;
+        Ok(())
+    }
+
+    /// Stops recording audio, if recording; the file is finalised even if this returns an error.
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        if !std::mem::take(&mut self.recording_audio) {
+            return Ok(());
+        }
+        // SAFETY: the instance is initialised, and not running thanks to the mutable reference.
+        match unsafe { GB_stop_audio_recording(&mut self.gb) } {
+            0 => Ok(()),
+            err_code => Err(io::Error::from_raw_os_error(err_code)),
+        }
+    }
+
+    pub fn is_recording_audio(&self) -> bool {
+        self.recording_audio
+    }
+
+    fn abort_recording(&mut self, err: io::Error) {
+        // The recording is broken anyway, but salvaging what we can is better than nothing.
+        let _ = self.stop_recording();
+        self.frontend.recording_aborted(err);
     }
 }
 
 /// Rendering.
 impl SameBoy {
-    fn update_screen(&mut self) {
-        let framebuffer = &self.framebuffer;
+    /// Returns the width and height of the screen, which includes the SGB border if there is one.
+    pub fn screen_size(&mut self) -> (u32, u32) {
         // SAFETY: the instance is properly initialised.
         let width = unsafe { GB_get_screen_width(&mut self.gb) };
         // SAFETY: ditto.
         let height = unsafe { GB_get_screen_height(&mut self.gb) };
-        let pixbuf =
-            SharedPixelBuffer::clone_from_slice(bytemuck::cast_slice(framebuffer), width, height);
-        let main_window = self.main_window.clone();
-        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
-        let _ = slint::invoke_from_event_loop(move || {
-            if let Some(main_window) = main_window.upgrade() {
-                main_window.set_screen(slint::Image::from_rgba8(pixbuf));
-            }
-        });
+        (width, height)
+    }
+
+    /// Returns the last frame that was completely drawn, e.g. for screenshots.
+    pub fn screen_pixels(&self) -> SharedPixelBuffer<Rgba8Pixel> {
+        self.last_frame.clone()
+    }
+
+    /// Returns a copy of the framebuffer, which may be halfway drawn.
+    fn live_pixels(&mut self) -> SharedPixelBuffer<Rgba8Pixel> {
+        let (width, height) = self.screen_size();
+        SharedPixelBuffer::clone_from_slice(bytemuck::cast_slice(&self.framebuffer), width, height)
+    }
+
+    fn update_screen(&mut self) {
+        // While stepping through the code, it's useful to see the frame being drawn.
+        let pixbuf = self.live_pixels();
+        self.frontend.update_screen(pixbuf);
     }
 
     pub fn render_everything(&mut self) {
         self.update_screen();
-        // TODO: tilemap, etc.
+        if let Some(settings) = self.frontend.vram_viewer() {
+            let images = self.draw_vram(settings);
+            self.frontend.update_vram_viewer(images);
+        }
+        if self.frontend.sprite_viewer_shown() {
+            let objects = self.objects();
+            self.frontend.update_sprite_viewer(objects);
+        }
+        if self.frontend.palette_viewer_shown() {
+            let palettes = self.palettes();
+            self.frontend.update_palette_viewer(palettes);
+        }
+        if let Some(settings) = self.frontend.memory_viewer() {
+            let page = self.memory_page(settings);
+            self.frontend.update_memory_viewer(page);
+        }
+        if self.frontend.cpu_panel_shown() {
+            let state = self.cpu_state();
+            self.frontend.update_cpu_panel(state);
+        }
+    }
+}
+
+/// What the VRAM viewer shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VramViewerSettings {
+    pub map: GB_map_type_t,
+    pub tileset: GB_tileset_type_t,
+    pub palette_type: GB_palette_type_t,
+    pub palette_index: u8,
+    /// Which VRAM bank's tiles the tileset shows; only the CGB has bank 1.
+    pub bank: u8,
+}
+impl Default for VramViewerSettings {
+    fn default() -> Self {
+        Self {
+            map: GB_map_type_t_GB_MAP_AUTO,
+            tileset: GB_tileset_type_t_GB_TILESET_AUTO,
+            palette_type: GB_palette_type_t_GB_PALETTE_AUTO,
+            palette_index: 0,
+            bank: 0,
+        }
+    }
+}
+
+pub struct VramImages {
+    /// 128x192, i.e. all 384 tiles of a VRAM bank, 16 per row.
+    pub tileset: SharedPixelBuffer<Rgba8Pixel>,
+    /// 256x256, with the viewport and window outlined.
+    pub tilemap: SharedPixelBuffer<Rgba8Pixel>,
+}
+
+/// Colours of the outlines drawn over the tilemap, in the framebuffer's format.
+const VIEWPORT_OUTLINE: u32 = u32::from_ne_bytes([0xFF, 0x20, 0x20, 0xFF]);
+const WINDOW_OUTLINE: u32 = u32::from_ne_bytes([0x20, 0x60, 0xFF, 0xFF]);
+
+/// The contents of OAM, as seen by the PPU.
+pub struct Objects {
+    pub objects: Vec<ObjectInfo>,
+    /// 8 or 16, depending on LCDC.
+    pub height: u8,
+    /// Whether the attributes must be interpreted as in CGB mode.
+    pub cgb_mode: bool,
+    /// Where the Game Boy's screen is within the framebuffer; not at the origin if there is an SGB border.
+    pub screen_origin: (u32, u32),
+}
+
+pub struct ObjectInfo {
+    /// 8 pixels wide, and as tall as all objects.
+    pub image: SharedPixelBuffer<Rgba8Pixel>,
+    /// As in OAM, i.e. offset by 8 from the screen's left edge.
+    pub x: u8,
+    /// As in OAM, i.e. offset by 16 from the screen's top edge.
+    pub y: u8,
+    pub tile: u8,
+    pub flags: u8,
+    pub oam_addr: u16,
+    /// Whether the object isn't displayed because of the 10 objects per line limit.
+    pub dropped: bool,
+}
+impl ObjectInfo {
+    /// The object's slot in OAM, from 0 to 39.
+    pub fn index(&self) -> u16 {
+        (self.oam_addr - 0xFE00) / 4
+    }
+
+    /// Whether the object is within the screen's bounds.
+    pub fn on_screen(&self, height: u8) -> bool {
+        (1..168).contains(&self.x) && u16::from(self.y) + u16::from(height) > 16 && self.y < 160
+    }
+}
+
+/// The palettes in use, either the CGB's palette RAM or the DMG's palette registers.
+pub struct Palettes {
+    pub cgb: bool,
+    /// 8 palettes on CGB, 1 (BGP) on DMG.
+    pub background: Vec<[PaletteColor; 4]>,
+    /// 8 palettes on CGB, 2 (OBP0 and OBP1) on DMG.
+    pub objects: Vec<[PaletteColor; 4]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PaletteColor {
+    pub value: ColorValue,
+    /// What the colour looks like on screen (after colour correction), in the framebuffer's format.
+    pub output: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ColorValue {
+    /// A CGB colour, as stored in palette RAM.
+    Rgb555(u16),
+    /// A DMG shade, from 0 (lightest) to 3 (darkest).
+    Shade(u8),
+}
+
+/// What the memory viewer shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryViewerSettings {
+    pub region: MemoryRegion,
+    pub bank: u16,
+    /// The address of the first byte shown; it's clamped to the bank, and aligned to a row.
+    pub start: u16,
+}
+
+/// A screenful of memory, as shown by the memory viewer.
+#[derive(Debug, Clone)]
+pub struct MemoryPage {
+    /// What was requested, except clamped to what exists.
+    pub settings: MemoryViewerSettings,
+    /// 0 if the region doesn't exist.
+    pub nb_banks: u16,
+    /// Starts at `settings.start`; empty if the region doesn't exist (e.g. no cartridge RAM).
+    pub bytes: Vec<u8>,
+    /// The names of the symbols pointing into the page, by offset from its start.
+    /// Only banks that are currently mapped can have symbols.
+    pub symbols: Vec<(usize, String)>,
+}
+
+/// How many bytes the memory viewer shows at a time, and per row.
+pub const MEMORY_PAGE_SIZE: usize = 0x100;
+pub const MEMORY_ROW_SIZE: usize = 0x10;
+
+/// Debug views.
+impl SameBoy {
+    pub fn memory_page(&mut self, settings: MemoryViewerSettings) -> MemoryPage {
+        let region = settings.region;
+        let (nb_banks, bank, mapped) = match region.direct_access() {
+            None => (1, 0, true),
+            Some(access) => match self.direct_access_banked(access) {
+                None => (0, 0, false),
+                Some((buffer, mapped_bank)) => {
+                    let (bank_size, _) = region.bank_layout(0);
+                    let nb_banks = buffer.len().div_ceil(bank_size) as u16;
+                    let bank = settings.bank.min(nb_banks - 1);
+                    // Bank 0 of ROM and WRAM is always mapped, at its own address.
+                    let fixed = region.bank_layout(0).1 != region.bank_layout(1).1;
+                    (nb_banks, bank, bank == mapped_bank || fixed && bank == 0)
+                }
+            },
+        };
+
+        let range = region.address_range(bank);
+        let start =
+            settings.start.clamp(*range.start(), *range.end()) & !(MEMORY_ROW_SIZE as u16 - 1);
+        let end = start
+            .saturating_add(MEMORY_PAGE_SIZE as u16 - 1)
+            .min(*range.end());
+        let bytes = match region.direct_access() {
+            None => (start..=end).map(|addr| self.read_memory(addr)).collect(),
+            Some(access) => {
+                let (bank_size, base) = region.bank_layout(bank);
+                let first = usize::from(bank) * bank_size + usize::from(start - base);
+                let len = usize::from(end - start) + 1;
+                self.direct_access(access)
+                    .and_then(|buffer| buffer.get(first..))
+                    .map(|bytes| bytes.iter().take(len).copied().collect())
+                    .unwrap_or_default()
+            }
+        };
+        let symbols = if mapped {
+            (start..=end)
+                .filter_map(|addr| Some((usize::from(addr - start), self.symbol_at(addr)?)))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        MemoryPage {
+            settings: MemoryViewerSettings {
+                region,
+                bank,
+                start,
+            },
+            nb_banks,
+            bytes,
+            symbols,
+        }
+    }
+
+    pub fn palettes(&mut self) -> Palettes {
+        // SAFETY: the instance is initialised.
+        if unsafe { GB_is_cgb(&self.gb) } {
+            // Even in DMG compatibility mode, the colours come from palette RAM.
+            let mut cgb_palettes = |access| {
+                let ram = self
+                    .direct_access(access)
+                    .map(|ram| ram.to_vec())
+                    .unwrap_or_default();
+                ram.chunks_exact(8)
+                    .map(|palette| {
+                        std::array::from_fn(|i| {
+                            let color = u16::from_le_bytes([palette[i * 2], palette[i * 2 + 1]]);
+                            PaletteColor {
+                                value: ColorValue::Rgb555(color),
+                                // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`).
+                                output: unsafe { GB_convert_rgb15(&mut self.gb, color, false) },
+                            }
+                        })
+                    })
+                    .collect()
+            };
+            Palettes {
+                cgb: true,
+                background: cgb_palettes(GB_direct_access_t_GB_DIRECT_ACCESS_BGP),
+                objects: cgb_palettes(GB_direct_access_t_GB_DIRECT_ACCESS_OBP),
+            }
+        } else {
+            // SAFETY: the instance is initialised; SameBoy always has a palette set, and it lives at least as long
+            //         as the instance.
+            let colors = unsafe { (*GB_get_palette(&mut self.gb)).colors };
+            let mut dmg_palette = |addr| {
+                let register = self.read_memory(addr);
+                std::array::from_fn(|i| {
+                    let shade = register >> (i * 2) & 3;
+                    // The palette goes from darkest to lightest.
+                    let color = colors[usize::from(3 - shade)];
+                    PaletteColor {
+                        value: ColorValue::Shade(shade),
+                        output: u32::from_ne_bytes([color.r, color.g, color.b, 0xFF]),
+                    }
+                })
+            };
+            Palettes {
+                cgb: false,
+                background: vec![dmg_palette(0xFF47)],
+                objects: vec![dmg_palette(0xFF48), dmg_palette(0xFF49)],
+            }
+        }
+    }
+
+    pub fn objects(&mut self) -> Objects {
+        /// OAM holds this many objects.
+        const NB_OBJECTS: usize = 40;
+        const OAM_BASE: u16 = 0xFE00;
+
+        // SameBoy only reports (and draws) the objects that are on a visible line, in the order it scans them.
+        let mut infos = [MaybeUninit::<GB_oam_info_t>::uninit(); NB_OBJECTS];
+        let mut height = 0;
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`);
+        //         the buffer is large enough for all of OAM.
+        let count = unsafe { GB_get_oam_info(&mut self.gb, infos[0].as_mut_ptr(), &mut height) };
+        let infos: Vec<_> = infos[..usize::from(count)]
+            .iter()
+            .map(|info| {
+                // SAFETY: SameBoy initialised the first `count` entries.
+                unsafe { info.assume_init_ref() }
+            })
+            .collect();
+
+        let mut oam = [0; NB_OBJECTS * 4];
+        if let Some(buffer) = self.direct_access(GB_direct_access_t_GB_DIRECT_ACCESS_OAM) {
+            let len = oam.len().min(buffer.len());
+            oam[..len].copy_from_slice(&buffer[..len]);
+        }
+        // SAFETY: the instance is initialised.
+        let cgb_mode = unsafe { GB_is_cgb_in_cgb_mode(&mut self.gb) };
+        let palettes = self.palettes().objects;
+        let vram = self
+            .direct_access(GB_direct_access_t_GB_DIRECT_ACCESS_VRAM)
+            .map_or(&[][..], |buffer| &*buffer);
+        let objects = oam
+            .chunks_exact(4)
+            .zip((OAM_BASE..).step_by(4))
+            .map(|(entry, oam_addr)| {
+                let info = infos.iter().find(|info| info.oam_addr == oam_addr);
+                ObjectInfo {
+                    image: match info {
+                        Some(info) => SharedPixelBuffer::clone_from_slice(
+                            bytemuck::cast_slice(&info.image[..8 * usize::from(height)]),
+                            8,
+                            height.into(),
+                        ),
+                        // SameBoy didn't draw it, as it isn't on a visible line.
+                        None => SharedPixelBuffer::clone_from_slice(
+                            bytemuck::cast_slice(&draw_object(
+                                vram, entry, height, cgb_mode, &palettes,
+                            )),
+                            8,
+                            height.into(),
+                        ),
+                    },
+                    y: entry[0],
+                    x: entry[1],
+                    tile: entry[2],
+                    flags: entry[3],
+                    oam_addr,
+                    dropped: info.is_some_and(|info| info.obscured_by_line_limit),
+                }
+            })
+            .collect();
+
+        let (width, screen_height) = self.screen_size();
+        Objects {
+            objects,
+            height,
+            cgb_mode,
+            screen_origin: ((width - 160) / 2, (screen_height - 144) / 2),
+        }
+    }
+
+    pub fn draw_vram(&mut self, settings: VramViewerSettings) -> VramImages {
+        /// `GB_draw_tileset` draws both banks side by side.
+        const TILESET_WIDTH: usize = 256;
+        const TILESET_HEIGHT: usize = 192;
+        const BANK_WIDTH: usize = TILESET_WIDTH / 2;
+        const TILEMAP_SIZE: usize = 256;
+
+        let mut tileset = vec![0; TILESET_WIDTH * TILESET_HEIGHT];
+        // SAFETY: the instance is initialised, and either not running, or waiting for a debugger command, which allows this (see `DebuggerRequest`);
+        //         the buffer is as large as SameBoy requires.
+        unsafe {
+            GB_draw_tileset(
+                &mut self.gb,
+                tileset.as_mut_ptr(),
+                settings.palette_type,
+                settings.palette_index,
+            )
+        };
+        let bank: Vec<u32> = tileset
+            .chunks_exact(TILESET_WIDTH)
+            .flat_map(|row| &row[usize::from(settings.bank) * BANK_WIDTH..][..BANK_WIDTH])
+            .copied()
+            .collect();
+
+        let mut tilemap = vec![0; TILEMAP_SIZE * TILEMAP_SIZE];
+        // SAFETY: ditto.
+        unsafe {
+            GB_draw_tilemap(
+                &mut self.gb,
+                tilemap.as_mut_ptr(),
+                settings.palette_type,
+                settings.palette_index,
+                settings.map,
+                settings.tileset,
+            )
+        };
+        self.outline_viewport(&mut tilemap, settings.map);
+
+        VramImages {
+            tileset: SharedPixelBuffer::clone_from_slice(
+                bytemuck::cast_slice(&bank),
+                BANK_WIDTH as u32,
+                TILESET_HEIGHT as u32,
+            ),
+            tilemap: SharedPixelBuffer::clone_from_slice(
+                bytemuck::cast_slice(&tilemap),
+                TILEMAP_SIZE as u32,
+                TILEMAP_SIZE as u32,
+            ),
+        }
+    }
+
+    /// Outlines the parts of a 256x256 tilemap that are visible through the background and window.
+    fn outline_viewport(&mut self, tilemap: &mut [u32], map: GB_map_type_t) {
+        const LCDC_BG_MAP: u8 = 1 << 3;
+        const LCDC_WIN_ENABLE: u8 = 1 << 5;
+        const LCDC_WIN_MAP: u8 = 1 << 6;
+
+        let lcdc = self.read_memory(0xFF40);
+        let scy = self.read_memory(0xFF42);
+        let scx = self.read_memory(0xFF43);
+        let wy = self.read_memory(0xFF4A);
+        let wx = self.read_memory(0xFF4B);
+        let shows_9c00 = match map {
+            GB_map_type_t_GB_MAP_9800 => false,
+            GB_map_type_t_GB_MAP_9C00 => true,
+            _ => lcdc & LCDC_BG_MAP != 0,
+        };
+
+        let mut outline = |x: u8, y: u8, width: u8, height: u8, color| {
+            let mut plot = |dx: u8, dy: u8| {
+                let (x, y) = (x.wrapping_add(dx), y.wrapping_add(dy));
+                tilemap[usize::from(y) * 256 + usize::from(x)] = color;
+            };
+            for dx in 0..width {
+                plot(dx, 0);
+                plot(dx, height - 1);
+            }
+            for dy in 0..height {
+                plot(0, dy);
+                plot(width - 1, dy);
+            }
+        };
+
+        if (lcdc & LCDC_BG_MAP != 0) == shows_9c00 {
+            outline(scx, scy, 160, 144, VIEWPORT_OUTLINE);
+        }
+        // The window's top-left corner is at (WX - 7, WY) on screen, and it extends to the bottom-right corner.
+        if lcdc & LCDC_WIN_ENABLE != 0
+            && (lcdc & LCDC_WIN_MAP != 0) == shows_9c00
+            && wx < 167
+            && wy < 144
+        {
+            let width = 167 - wx.max(7);
+            outline(0, 0, width, 144 - wy, WINDOW_OUTLINE);
+        }
+    }
+}
+
+/// Draws an object from its OAM entry, the way SameBoy does for those on a visible line.
+fn draw_object(
+    vram: &[u8],
+    entry: &[u8],
+    height: u8,
+    cgb_mode: bool,
+    palettes: &[[PaletteColor; 4]],
+) -> Vec<u32> {
+    const TILE_SIZE: usize = 16;
+    const VRAM_BANK_SIZE: usize = 0x2000;
+
+    let (tile, flags) = (entry[2], entry[3]);
+    let (palette, bank) = if cgb_mode {
+        (flags & 7, flags >> 3 & 1)
+    } else {
+        (flags >> 4 & 1, 0)
+    };
+    // Tall objects ignore the tile number's low bit, and use the pair of tiles it's in.
+    let tile = if height == 16 { tile & !1 } else { tile };
+    let colors = palettes
+        .get(usize::from(palette))
+        .map(|palette| palette.map(|color| color.output))
+        .unwrap_or_default();
+
+    let height = usize::from(height);
+    let mut pixels = vec![0; 8 * height];
+    for (y, row) in pixels.chunks_exact_mut(8).enumerate() {
+        let line = if flags & 0x40 != 0 { height - 1 - y } else { y };
+        let addr = usize::from(bank) * VRAM_BANK_SIZE + usize::from(tile) * TILE_SIZE + line * 2;
+        let (Some(&low), Some(&high)) = (vram.get(addr), vram.get(addr + 1)) else {
+            continue;
+        };
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let bit = if flags & 0x20 != 0 { x } else { 7 - x };
+            let color = (low >> bit & 1) | (high >> bit & 1) << 1;
+            *pixel = colors[usize::from(color)];
+        }
+    }
+    pixels
+}
+
+/// What the UI sends to SameBoy's debugger.
+pub enum DebuggerInput {
+    Command(DebuggerCmdStr),
+    /// Only sent while the debugger waits for a command; see [`Halt`].
+    Request(DebuggerRequest),
+}
+
+/// What the UI can do with the emulator even while the debugger waits for a command: looking at it, and editing
+/// registers and memory. SameBoy's own debugger commands do as much from its input callback, unlike, say, resetting.
+pub enum DebuggerRequest {
+    /// Redraws the screen and debug views.
+    Render,
+    SetRegister(Register, u16),
+    /// Replies whether the address exists.
+    WriteMemory {
+        region: MemoryRegion,
+        bank: u16,
+        addr: u16,
+        value: u8,
+        reply: Sender<bool>,
+    },
+    /// Replies with [`SameBoy::debugger_completions`].
+    Complete(String, Sender<Vec<String>>),
+    /// Replies with [`SameBoy::evaluate`].
+    Evaluate(String, Sender<Option<u16>>),
+}
+impl DebuggerRequest {
+    pub fn handle(self, sameboy: &mut SameBoy) {
+        // Replies are ignored if the requester has stopped waiting.
+        match self {
+            Self::Render => sameboy.render_everything(),
+            Self::SetRegister(register, value) => {
+                sameboy.set_register(register, value);
+                // PC may have moved, and the debugger will resume from there.
+                if sameboy.halt.is_debugger_stopped() {
+                    sameboy.show_disassembly();
+                }
+                sameboy.render_everything();
+            }
+            Self::WriteMemory {
+                region,
+                bank,
+                addr,
+                value,
+                reply,
+            } => {
+                let written = sameboy.write_memory(region, bank, addr, value);
+                sameboy.render_everything();
+                let _ = reply.send(written);
+            }
+            Self::Complete(input, reply) => {
+                let _ = reply.send(sameboy.debugger_completions(&input));
+            }
+            Self::Evaluate(expression, reply) => {
+                let _ = reply.send(sameboy.evaluate(&expression));
+            }
+        }
     }
 }
 
@@ -0,0 +1,12 @@
+// Accessors for parts of SameBoy's state that its API doesn't expose.
+// This is compiled along with SameBoy (thus with `GB_INTERNAL`), so it can see the instance's fields.
+
+#include <Core/gb.h>
+
+bool aythya_get_ime(GB_gameboy_t *gb) {
+    return gb->ime;
+}
+
+bool aythya_is_halted(GB_gameboy_t *gb) {
+    return gb->halted;
+}
@@ -0,0 +1,144 @@
+//! Writing screen contents to image files.
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use slint::{Rgba8Pixel, SharedPixelBuffer};
+
+use crate::{DebuggerStopped, Emulation, settings::Settings, show_error};
+
+/// The largest scaling factor offered for screenshots.
+pub const MAX_SCALE: u32 = 4;
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    NoRom,
+    Stopped(DebuggerStopped),
+    Db(rusqlite::Error),
+    Write(PathBuf, png::EncodingError),
+}
+impl Display for ScreenshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoRom => write!(f, "No ROM is loaded."),
+            Self::Stopped(err) => write!(f, "{err}"),
+            Self::Db(err) => write!(f, "Database error: {err}"),
+            Self::Write(path, err) => write!(f, "Unable to write {}: {err}", path.display()),
+        }
+    }
+}
+impl std::error::Error for ScreenshotError {}
+impl From<rusqlite::Error> for ScreenshotError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Db(err)
+    }
+}
+impl From<DebuggerStopped> for ScreenshotError {
+    fn from(err: DebuggerStopped) -> Self {
+        Self::Stopped(err)
+    }
+}
+
+/// Saves a screenshot, named after the ROM and the current time, in the screenshot directory
+/// (or next to the ROM if there is none), scaled as per the settings.
+pub fn take(emulation: &Emulation, settings: &Settings) {
+    if let Err(err) = save(emulation, settings) {
+        show_error("Unable to save screenshot", &err);
+    }
+}
+
+fn save(emulation: &Emulation, settings: &Settings) -> Result<PathBuf, ScreenshotError> {
+    let (pixels, rom, rom_path) = emulation.stopped(|sameboy| {
+        (
+            sameboy.screen_pixels(),
+            sameboy.rom_info(),
+            sameboy.rom_path().map(Path::to_owned),
+        )
+    })?;
+    let (Some(rom), Some(rom_path)) = (rom, rom_path) else {
+        return Err(ScreenshotError::NoRom);
+    };
+
+    let dir = match settings.screenshot_dir()? {
+        Some(dir) => dir,
+        None => rom_path.parent().map(Path::to_owned).unwrap_or_default(),
+    };
+    let path = unused_path(&dir, &file_stem(&rom.title, &settings.file_timestamp()?));
+    let pixels = scale(&pixels, settings.screenshot_scale()?);
+    write_png(&path, &pixels).map_err(|err| ScreenshotError::Write(path.clone(), err))?;
+    Ok(path)
+}
+
+/// Builds a file name (sans extension) from the ROM's title, made safe for all file systems.
+fn file_stem(rom_title: &str, timestamp: &str) -> String {
+    let title: String = rom_title
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, ' ' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if title.is_empty() {
+        format!("Screenshot {timestamp}")
+    } else {
+        format!("{title} {timestamp}")
+    }
+}
+
+/// Returns a path in `dir` named after `stem` that doesn't exist yet, in case several screenshots are taken
+/// within the same second.
+fn unused_path(dir: &Path, stem: &str) -> PathBuf {
+    let mut path = dir.join(format!("{stem}.png"));
+    let mut index = 2;
+    while path.exists() {
+        path = dir.join(format!("{stem} ({index}).png"));
+        index += 1;
+    }
+    path
+}
+
+/// Scales the pixels up by an integer factor, without any filtering.
+pub fn scale(pixels: &SharedPixelBuffer<Rgba8Pixel>, factor: u32) -> SharedPixelBuffer<Rgba8Pixel> {
+    if factor <= 1 {
+        return pixels.clone();
+    }
+    let width = pixels.width() * factor;
+    let mut scaled = SharedPixelBuffer::new(width, pixels.height() * factor);
+    let src = pixels.as_slice();
+    for (y, row) in scaled
+        .make_mut_slice()
+        .chunks_exact_mut(width as usize)
+        .enumerate()
+    {
+        let src_row = y / factor as usize * pixels.width() as usize;
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = src[src_row + x / factor as usize];
+        }
+    }
+    scaled
+}
+
+/// Writes the pixels to a PNG file, overwriting it if it exists.
+pub fn write_png(
+    path: &Path,
+    pixels: &SharedPixelBuffer<Rgba8Pixel>,
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        pixels.width(),
+        pixels.height(),
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels.as_bytes())?;
+    writer.finish()
+}
@@ -0,0 +1,335 @@
+//! Persistent settings, stored in a SQLite database.
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::{
+    Connection, OptionalExtension,
+    types::{FromSql, ToSql},
+};
+
+use slint::{Rgba8Pixel, SharedPixelBuffer};
+
+use crate::{
+    MAIN_WINDOW_NAME,
+    input::{Button, Keymap},
+    prompt,
+    sameboy::{ModelChoice, RomInfo},
+    screenshot,
+};
+
+/// Each entry brings the database's schema from version `index` to version `index + 1`.
+/// The current version is tracked through SQLite's `user_version` pragma.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE key_bindings (
+        key TEXT NOT NULL,
+        button TEXT NOT NULL,
+        PRIMARY KEY (key, button)
+    );",
+    "CREATE TABLE settings (
+        name TEXT PRIMARY KEY NOT NULL,
+        value
+    );",
+    "CREATE TABLE save_states (
+        slot INTEGER PRIMARY KEY NOT NULL,
+        rom_title TEXT NOT NULL,
+        rom_crc32 INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        thumbnail_width INTEGER NOT NULL,
+        thumbnail_height INTEGER NOT NULL,
+        thumbnail BLOB NOT NULL,
+        state BLOB NOT NULL
+    );",
+    "CREATE TABLE debugger_history (
+        id INTEGER PRIMARY KEY NOT NULL,
+        command TEXT NOT NULL
+    );",
+];
+
+/// Information about a save state, minus the state itself.
+pub struct SaveStateInfo {
+    pub rom: RomInfo,
+    /// When the state was saved, formatted in local time.
+    pub saved_at: String,
+    pub thumbnail: SharedPixelBuffer<Rgba8Pixel>,
+}
+
+const DEFAULT_REWIND_LENGTH: f64 = 10.0;
+const DEFAULT_LOG_LENGTH: u32 = 10_000;
+
+pub struct Settings {
+    db: Connection,
+}
+
+/// Creation.
+impl Settings {
+    /// Opens (or creates) the settings database in the user's configuration directory.
+    pub fn open() -> rusqlite::Result<Self> {
+        let path = match config_dir() {
+            Some(dir) => {
+                // If this fails, opening the database will too, and report a better error than we could.
+                let _ = std::fs::create_dir_all(&dir);
+                dir.join("settings.sqlite3")
+            }
+            // No idea where to put the file, so let's put it in the current directory.
+            None => PathBuf::from("settings.sqlite3"),
+        };
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Creates settings that won't be persisted, as a fallback if the database can't be opened.
+    pub fn in_memory() -> Self {
+        Self::init(Connection::open_in_memory().expect("Unable to create in-memory database"))
+            .expect("Unable to initialise in-memory database")
+    }
+
+    fn init(db: Connection) -> rusqlite::Result<Self> {
+        let version: usize = db.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let is_new = version == 0;
+        let tx = db.unchecked_transaction()?;
+        for migration in MIGRATIONS.iter().skip(version) {
+            tx.execute_batch(migration)?;
+        }
+        tx.pragma_update(None, "user_version", MIGRATIONS.len())?;
+        tx.commit()?;
+
+        let this = Self { db };
+        if is_new {
+            this.save_keymap(&Keymap::default())?;
+        }
+        Ok(this)
+    }
+}
+
+/// Key bindings.
+impl Settings {
+    pub fn load_keymap(&self) -> rusqlite::Result<Keymap> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT key, button FROM key_bindings ORDER BY rowid")?;
+        let mut keymap = Keymap::empty();
+        for row in stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })? {
+            let (key, button) = row?;
+            // Silently drop bindings we can't make sense of; they can only come from tampering with the DB.
+            if let Some(button) = Button::from_name(&button) {
+                keymap.bind(&key, button);
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn save_keymap(&self, keymap: &Keymap) -> rusqlite::Result<()> {
+        let tx = self.db.unchecked_transaction()?;
+        tx.execute("DELETE FROM key_bindings", [])?;
+        let mut stmt = tx.prepare("INSERT INTO key_bindings (key, button) VALUES (?1, ?2)")?;
+        for (key, button) in keymap.bindings() {
+            stmt.execute((key.as_str(), button.name()))?;
+        }
+        drop(stmt);
+        tx.commit()
+    }
+}
+
+/// Save states.
+impl Settings {
+    pub fn store_save_state(
+        &self,
+        slot: u8,
+        rom: &RomInfo,
+        thumbnail: &SharedPixelBuffer<Rgba8Pixel>,
+        state: &[u8],
+    ) -> rusqlite::Result<()> {
+        self.db
+            .execute(
+                "INSERT OR REPLACE INTO save_states
+                    (slot, rom_title, rom_crc32, timestamp, thumbnail_width, thumbnail_height, thumbnail, state)
+                    VALUES (?1, ?2, ?3, strftime('%s', 'now'), ?4, ?5, ?6, ?7)",
+                (
+                    slot,
+                    &rom.title,
+                    rom.crc32,
+                    thumbnail.width(),
+                    thumbnail.height(),
+                    thumbnail.as_bytes(),
+                    state,
+                ),
+            )
+            .map(|_| ())
+    }
+
+    pub fn save_state_info(&self, slot: u8) -> rusqlite::Result<Option<SaveStateInfo>> {
+        self.db
+            .query_row(
+                "SELECT rom_title, rom_crc32, datetime(timestamp, 'unixepoch', 'localtime'),
+                    thumbnail_width, thumbnail_height, thumbnail
+                    FROM save_states WHERE slot = ?1",
+                [slot],
+                |row| {
+                    let thumbnail: Vec<u8> = row.get(5)?;
+                    Ok(SaveStateInfo {
+                        rom: RomInfo {
+                            title: row.get(0)?,
+                            crc32: row.get(1)?,
+                        },
+                        saved_at: row.get(2)?,
+                        thumbnail: SharedPixelBuffer::clone_from_slice(
+                            bytemuck::cast_slice(&thumbnail),
+                            row.get(3)?,
+                            row.get(4)?,
+                        ),
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn save_state(&self, slot: u8) -> rusqlite::Result<Option<Vec<u8>>> {
+        self.db
+            .query_row(
+                "SELECT state FROM save_states WHERE slot = ?1",
+                [slot],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+}
+
+/// Debugger history.
+impl Settings {
+    /// Returns the debugger commands that were submitted, oldest first.
+    pub fn debugger_history(&self) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT command FROM debugger_history ORDER BY id")?;
+        stmt.query_map([], |row| row.get(0))?.collect()
+    }
+
+    /// Appends a command to the debugger history, forgetting the oldest ones if it's full.
+    pub fn push_debugger_history(&self, command: &str) -> rusqlite::Result<()> {
+        let tx = self.db.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO debugger_history (command) VALUES (?1)",
+            [command],
+        )?;
+        tx.execute(
+            "DELETE FROM debugger_history WHERE id NOT IN
+                (SELECT id FROM debugger_history ORDER BY id DESC LIMIT ?1)",
+            [prompt::HISTORY_LENGTH],
+        )?;
+        tx.commit()
+    }
+}
+
+/// Miscellaneous settings.
+impl Settings {
+    fn get<T: FromSql>(&self, name: &str) -> rusqlite::Result<Option<T>> {
+        self.db
+            .query_row(
+                "SELECT value FROM settings WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    fn set<T: ToSql>(&self, name: &str, value: Option<T>) -> rusqlite::Result<()> {
+        match value {
+            Some(value) => self.db.execute(
+                "INSERT OR REPLACE INTO settings (name, value) VALUES (?1, ?2)",
+                (name, value),
+            ),
+            None => self
+                .db
+                .execute("DELETE FROM settings WHERE name = ?1", [name]),
+        }
+        .map(|_| ())
+    }
+
+    /// Where battery saves are stored; `None` means next to the ROM.
+    pub fn save_dir(&self) -> rusqlite::Result<Option<PathBuf>> {
+        Ok(self.get::<String>("save_dir")?.map(PathBuf::from))
+    }
+
+    pub fn set_save_dir(&self, save_dir: Option<&Path>) -> rusqlite::Result<()> {
+        self.set("save_dir", save_dir.map(|dir| dir.to_string_lossy()))
+    }
+
+    pub fn model(&self) -> rusqlite::Result<ModelChoice> {
+        Ok(self
+            .get::<String>("model")?
+            .and_then(|name| ModelChoice::from_name(&name))
+            .unwrap_or(ModelChoice::Auto))
+    }
+
+    pub fn set_model(&self, model: ModelChoice) -> rusqlite::Result<()> {
+        self.set("model", Some(model.name()))
+    }
+
+    /// How far back rewinding can go, in seconds.
+    pub fn rewind_length(&self) -> rusqlite::Result<f64> {
+        Ok(self.get("rewind_length")?.unwrap_or(DEFAULT_REWIND_LENGTH))
+    }
+
+    pub fn set_rewind_length(&self, seconds: f64) -> rusqlite::Result<()> {
+        self.set("rewind_length", Some(seconds))
+    }
+
+    /// How many lines of the debugger log are kept.
+    pub fn log_length(&self) -> rusqlite::Result<u32> {
+        Ok(self.get("log_length")?.unwrap_or(DEFAULT_LOG_LENGTH))
+    }
+
+    pub fn set_log_length(&self, lines: u32) -> rusqlite::Result<()> {
+        self.set("log_length", Some(lines))
+    }
+
+    /// Where screenshots are stored; `None` means next to the ROM.
+    pub fn screenshot_dir(&self) -> rusqlite::Result<Option<PathBuf>> {
+        Ok(self.get::<String>("screenshot_dir")?.map(PathBuf::from))
+    }
+
+    pub fn set_screenshot_dir(&self, screenshot_dir: Option<&Path>) -> rusqlite::Result<()> {
+        self.set(
+            "screenshot_dir",
+            screenshot_dir.map(|dir| dir.to_string_lossy()),
+        )
+    }
+
+    /// By how much screenshots are scaled up.
+    pub fn screenshot_scale(&self) -> rusqlite::Result<u32> {
+        Ok(self
+            .get("screenshot_scale")?
+            .unwrap_or(1)
+            .clamp(1, screenshot::MAX_SCALE))
+    }
+
+    pub fn set_screenshot_scale(&self, scale: u32) -> rusqlite::Result<()> {
+        self.set("screenshot_scale", Some(scale))
+    }
+
+    /// Returns the current local time, formatted for use in file names.
+    // SQLite is the only thing we have around that knows about time zones, so let's put it to use.
+    pub fn file_timestamp(&self) -> rusqlite::Result<String> {
+        self.db.query_row(
+            "SELECT strftime('%Y-%m-%d %H-%M-%S', 'now', 'localtime')",
+            [],
+            |row| row.get(0),
+        )
+    }
+}
+
+/// Returns the directory where our settings should be stored, following each platform's conventions.
+fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join(MAIN_WINDOW_NAME))
+}
@@ -0,0 +1,150 @@
+//! Save state slots.
+
+use std::{fmt::Display, rc::Rc};
+
+use slint::{Image, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    DebuggerStopped, Emulation, MainWindow, StatesWindow,
+    sameboy::{RomInfo, SameBoy},
+    settings::Settings,
+    show_error,
+};
+
+/// Slots are numbered from 1 to this, inclusive.
+pub const NB_SLOTS: u8 = 10;
+
+#[derive(Debug)]
+pub enum StateError {
+    NoRom,
+    EmptySlot(u8),
+    WrongRom { state: RomInfo, loaded: RomInfo },
+    Rejected,
+    Stopped(DebuggerStopped),
+    Db(rusqlite::Error),
+}
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoRom => write!(f, "No ROM is loaded."),
+            Self::EmptySlot(slot) => write!(f, "Slot {slot} is empty."),
+            Self::WrongRom { state, loaded } => write!(
+                f,
+                "This state was saved from \"{}\" (CRC32 {:08x}), but \"{}\" (CRC32 {:08x}) is loaded.",
+                state.title, state.crc32, loaded.title, loaded.crc32,
+            ),
+            Self::Rejected => write!(f, "The emulator rejected the state, it may be corrupted."),
+            Self::Stopped(err) => write!(f, "{err}"),
+            Self::Db(err) => write!(f, "Database error: {err}"),
+        }
+    }
+}
+impl std::error::Error for StateError {}
+impl From<rusqlite::Error> for StateError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Db(err)
+    }
+}
+impl From<DebuggerStopped> for StateError {
+    fn from(err: DebuggerStopped) -> Self {
+        Self::Stopped(err)
+    }
+}
+
+/// Everything needed to act on save state slots from UI callbacks.
+#[derive(Clone)]
+pub struct StateSlots {
+    emulation: Emulation,
+    settings: Rc<Settings>,
+    main_window: Weak<MainWindow>,
+    states_window: Weak<StatesWindow>,
+}
+
+impl StateSlots {
+    pub fn new(
+        emulation: Emulation,
+        settings: Rc<Settings>,
+        main_window: Weak<MainWindow>,
+        states_window: Weak<StatesWindow>,
+    ) -> Self {
+        Self {
+            emulation,
+            settings,
+            main_window,
+            states_window,
+        }
+    }
+
+    /// Saves the emulator's state to a slot.
+    pub fn save(&self, slot: u8) {
+        match save(&self.emulation, &self.settings, slot) {
+            Ok(()) => self.refresh(),
+            Err(err) => show_error("Unable to save state", &err),
+        }
+    }
+
+    /// Loads the emulator's state from a slot, unless it was saved from a different ROM.
+    pub fn load(&self, slot: u8) {
+        if let Err(err) = load(&self.emulation, &self.settings, slot) {
+            show_error("Unable to load state", &err);
+        }
+    }
+
+    /// Updates the slots' descriptions in the UI.
+    pub fn refresh(&self) {
+        let mut labels = Vec::with_capacity(NB_SLOTS.into());
+        let mut rows = Vec::with_capacity(NB_SLOTS.into());
+        for slot in 1..=NB_SLOTS {
+            let (label, thumbnail, used) = match self.settings.save_state_info(slot) {
+                Ok(Some(info)) => (
+                    format!("{slot}: {} ({})", info.rom.title, info.saved_at),
+                    Image::from_rgba8(info.thumbnail),
+                    true,
+                ),
+                Ok(None) => (format!("{slot}: empty"), Image::default(), false),
+                Err(err) => (format!("{slot}: error ({err})"), Image::default(), false),
+            };
+            let label = SharedString::from(label);
+            labels.push(label.clone());
+            rows.push((label, thumbnail, used));
+        }
+        if let Some(main_window) = self.main_window.upgrade() {
+            main_window.set_state_slots(ModelRc::new(VecModel::from(labels)));
+        }
+        if let Some(states_window) = self.states_window.upgrade() {
+            states_window.set_slots(ModelRc::new(VecModel::from(rows)));
+        }
+    }
+}
+
+fn save(emulation: &Emulation, settings: &Settings, slot: u8) -> Result<(), StateError> {
+    let (rom, state, thumbnail) = emulation.stopped(|sameboy| {
+        let rom = sameboy.rom_info().ok_or(StateError::NoRom)?;
+        Ok::<_, StateError>((rom, sameboy.save_state(), sameboy.screen_pixels()))
+    })??;
+    settings.store_save_state(slot, &rom, &thumbnail, &state)?;
+    Ok(())
+}
+
+fn load(emulation: &Emulation, settings: &Settings, slot: u8) -> Result<(), StateError> {
+    let loaded = emulation
+        .stopped(SameBoy::rom_info)?
+        .ok_or(StateError::NoRom)?;
+    let info = settings
+        .save_state_info(slot)?
+        .ok_or(StateError::EmptySlot(slot))?;
+    if info.rom != loaded {
+        return Err(StateError::WrongRom {
+            state: info.rom,
+            loaded,
+        });
+    }
+    let state = settings
+        .save_state(slot)?
+        .ok_or(StateError::EmptySlot(slot))?;
+    if emulation.stopped(|sameboy| sameboy.load_state(&state))? {
+        Ok(())
+    } else {
+        Err(StateError::Rejected)
+    }
+}
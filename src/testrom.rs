@@ -0,0 +1,267 @@
+//! Recognising how common test ROMs report their results.
+//!
+//! - Mooneye's tests execute `ld b,b` once done, with the Fibonacci sequence in B, C, D, E, H and L on success,
+//!   or $42 in all of them on failure.
+//! - Blargg's tests print their output through the serial port, ending with "Passed" or "Failed";
+//!   the rest of that line (such as "Failed #2") may say more, so the result is only final once it ends.
+//! - Some of Blargg's tests also write a signature to cartridge RAM: $DE $B0 $61 at $A001-$A003,
+//!   a status byte at $A000 ($80 while running, then 0 on success or an error code),
+//!   and their output as a NUL-terminated string from $A004.
+
+use std::fmt::Display;
+
+/// The opcode of `ld b,b`, which Mooneye's tests use as a breakpoint.
+pub const MOONEYE_BREAKPOINT: u8 = 0x40;
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+
+const BLARGG_SIGNATURE_ADDR: u16 = 0xA001;
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_STATUS_ADDR: u16 = 0xA000;
+const BLARGG_STATUS_RUNNING: u8 = 0x80;
+const BLARGG_TEXT_ADDR: u16 = 0xA004;
+/// If the serial output's last line doesn't end, it's considered complete after this many checks without output.
+const SERIAL_QUIET_CHECKS: u8 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convention {
+    Mooneye,
+    BlarggSerial,
+    BlarggMemory,
+}
+impl Display for Convention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mooneye => write!(f, "Mooneye registers"),
+            Self::BlarggSerial => write!(f, "Blargg serial output"),
+            Self::BlarggMemory => write!(f, "Blargg memory signature"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub convention: Convention,
+    pub passed: bool,
+    /// Whatever the ROM had to say about it, possibly nothing.
+    pub details: String,
+}
+
+/// Watches what the ROM does for signs that it has finished running.
+#[derive(Debug, Default)]
+pub struct TestMonitor {
+    /// The bits of the serial byte currently being sent, MSB first.
+    serial_byte: u8,
+    nb_serial_bits: u8,
+    serial_output: String,
+    /// Whether the serial output said "Passed" or "Failed", while waiting for the end of that line.
+    serial_verdict: Option<bool>,
+    /// How many checks in a row saw no new serial output.
+    quiet_checks: u8,
+    /// Whether Blargg's status byte has been seen to indicate that the test is running;
+    /// this avoids picking up a result left over in a battery save.
+    blargg_running: bool,
+    outcome: Option<Outcome>,
+}
+
+impl TestMonitor {
+    pub fn outcome(&self) -> Option<&Outcome> {
+        self.outcome.as_ref()
+    }
+
+    /// Everything printed through the serial port so far.
+    pub fn serial_output(&self) -> &str {
+        &self.serial_output
+    }
+
+    /// To be called with each bit sent through the serial port.
+    pub fn serial_bit(&mut self, bit: bool) {
+        self.serial_byte = self.serial_byte << 1 | u8::from(bit);
+        self.nb_serial_bits += 1;
+        if self.nb_serial_bits < 8 {
+            return;
+        }
+        self.nb_serial_bits = 0;
+        self.serial_output.push(char::from(self.serial_byte));
+        self.quiet_checks = 0;
+
+        if self.outcome.is_some() {
+            return;
+        }
+        if let Some(passed) = self.serial_verdict {
+            if self.serial_byte == b'\n' {
+                self.conclude_serial(passed);
+            }
+        } else {
+            for (word, passed) in [("Passed", true), ("Failed", false)] {
+                if self.serial_output.contains(word) {
+                    self.serial_verdict = Some(passed);
+                }
+            }
+        }
+    }
+
+    /// To be called regularly (say, every frame), so that a result is reported even if its line never ends.
+    pub fn check_serial(&mut self) {
+        self.quiet_checks = self.quiet_checks.saturating_add(1);
+        if self.outcome.is_none()
+            && let Some(passed) = self.serial_verdict
+            && self.quiet_checks >= SERIAL_QUIET_CHECKS
+        {
+            self.conclude_serial(passed);
+        }
+    }
+
+    fn conclude_serial(&mut self, passed: bool) {
+        self.outcome = Some(Outcome {
+            convention: Convention::BlarggSerial,
+            passed,
+            details: self.serial_output.trim().to_owned(),
+        });
+    }
+
+    /// To be called when `ld b,b` is executed, with the values of B, C, D, E, H and L.
+    pub fn mooneye_breakpoint(&mut self, registers: [u8; 6]) {
+        let passed = match registers {
+            MOONEYE_PASS => true,
+            MOONEYE_FAIL => false,
+            _ => return, // Just a regular `ld b,b`, then.
+        };
+        self.outcome.get_or_insert(Outcome {
+            convention: Convention::Mooneye,
+            passed,
+            details: String::new(),
+        });
+    }
+
+    /// Checks for Blargg's signature in cartridge RAM; `read` must read from the memory bus.
+    pub fn check_memory(&mut self, mut read: impl FnMut(u16) -> u8) {
+        if self.outcome.is_some() {
+            return;
+        }
+        let signature = [0, 1, 2].map(|offset| read(BLARGG_SIGNATURE_ADDR + offset));
+        if signature != BLARGG_SIGNATURE {
+            return;
+        }
+
+        let status = read(BLARGG_STATUS_ADDR);
+        if status == BLARGG_STATUS_RUNNING {
+            self.blargg_running = true;
+        } else if self.blargg_running && status < BLARGG_STATUS_RUNNING {
+            // (Statuses above $80 are requests to the tester, such as pressing the reset button.)
+            let text: Vec<u8> = (BLARGG_TEXT_ADDR..0xC000)
+                .map(&mut read)
+                .take_while(|&byte| byte != 0)
+                .collect();
+            self.outcome = Some(Outcome {
+                convention: Convention::BlarggMemory,
+                passed: status == 0,
+                details: String::from_utf8_lossy(&text).trim().to_owned(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(monitor: &mut TestMonitor, text: &str) {
+        for byte in text.bytes() {
+            for bit in (0..8).rev() {
+                monitor.serial_bit(byte & 1 << bit != 0);
+            }
+        }
+    }
+
+    /// Cartridge RAM as Blargg's tests leave it, with the given status and text.
+    fn blargg_ram(status: u8, text: &str) -> impl FnMut(u16) -> u8 {
+        let text = text.as_bytes().to_owned();
+        move |addr| match addr {
+            BLARGG_STATUS_ADDR => status,
+            0xA001..=0xA003 => BLARGG_SIGNATURE[usize::from(addr - BLARGG_SIGNATURE_ADDR)],
+            _ => text
+                .get(usize::from(addr - BLARGG_TEXT_ADDR))
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+
+    #[test]
+    fn mooneye_pass() {
+        let mut monitor = TestMonitor::default();
+        monitor.mooneye_breakpoint(MOONEYE_PASS);
+        let outcome = monitor.outcome().unwrap();
+        assert_eq!(outcome.convention, Convention::Mooneye);
+        assert!(outcome.passed);
+    }
+
+    #[test]
+    fn mooneye_fail() {
+        let mut monitor = TestMonitor::default();
+        monitor.mooneye_breakpoint(MOONEYE_FAIL);
+        assert!(!monitor.outcome().unwrap().passed);
+    }
+
+    #[test]
+    fn mooneye_ignores_other_ld_b_b() {
+        let mut monitor = TestMonitor::default();
+        monitor.mooneye_breakpoint([1, 2, 3, 4, 5, 6]);
+        assert!(monitor.outcome().is_none());
+    }
+
+    #[test]
+    fn blargg_serial_pass() {
+        let mut monitor = TestMonitor::default();
+        print(
+            &mut monitor,
+            "cpu_instrs\n\n01:ok  02:ok\n\nPassed all tests\n",
+        );
+        let outcome = monitor.outcome().unwrap();
+        assert_eq!(outcome.convention, Convention::BlarggSerial);
+        assert!(outcome.passed);
+        assert!(outcome.details.ends_with("Passed all tests"));
+    }
+
+    #[test]
+    fn blargg_serial_fail_waits_for_end_of_line() {
+        let mut monitor = TestMonitor::default();
+        print(&mut monitor, "02-interrupts\n\nFailed");
+        assert!(monitor.outcome().is_none());
+        print(&mut monitor, " #2\n");
+        let outcome = monitor.outcome().unwrap();
+        assert!(!outcome.passed);
+        assert_eq!(outcome.details, "02-interrupts\n\nFailed #2");
+    }
+
+    #[test]
+    fn blargg_serial_without_newline() {
+        let mut monitor = TestMonitor::default();
+        print(&mut monitor, "Failed #3");
+        for _ in 1..SERIAL_QUIET_CHECKS {
+            monitor.check_serial();
+        }
+        assert!(monitor.outcome().is_none());
+        monitor.check_serial();
+        assert_eq!(monitor.outcome().unwrap().details, "Failed #3");
+    }
+
+    #[test]
+    fn blargg_memory() {
+        let mut monitor = TestMonitor::default();
+        monitor.check_memory(blargg_ram(BLARGG_STATUS_RUNNING, ""));
+        assert!(monitor.outcome().is_none());
+        monitor.check_memory(blargg_ram(3, "dmg_sound\n\nFailed #3\n"));
+        let outcome = monitor.outcome().unwrap();
+        assert_eq!(outcome.convention, Convention::BlarggMemory);
+        assert!(!outcome.passed);
+        assert_eq!(outcome.details, "dmg_sound\n\nFailed #3");
+    }
+
+    #[test]
+    fn blargg_memory_ignores_leftover_result() {
+        let mut monitor = TestMonitor::default();
+        monitor.check_memory(blargg_ram(0, "Passed\n"));
+        assert!(monitor.outcome().is_none());
+    }
+}
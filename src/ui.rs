@@ -0,0 +1,869 @@
+//! The graphical frontend, which forwards the emulator's output to the Slint windows.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{BTreeSet, VecDeque},
+    fmt::Write,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use slint::{
+    Model, ModelNotify, ModelRc, ModelTracker, Rgba8Pixel, SharedPixelBuffer, SharedString,
+    VecModel, Weak,
+};
+
+use crate::{
+    DebuggerWindow, DisassemblyRow, LogKind, LogLine, LogSpan, MainWindow, MemoryCell, MemoryRow,
+    MemoryWindow, PaletteRow, PaletteSwatch, PalettesWindow, RegisterRow, ScreenRect, SpriteRow,
+    SpritesWindow, VramWindow,
+    sameboy::{
+        ColorValue, CpuState, DisassemblyLine, Frontend, GB_log_attributes_t,
+        GB_log_attributes_t_GB_LOG_BOLD, GB_log_attributes_t_GB_LOG_DASHED_UNDERLINE,
+        GB_log_attributes_t_GB_LOG_UNDERLINE, GB_log_attributes_t_GB_LOG_UNDERLINE_MASK,
+        GB_map_type_t, GB_map_type_t_GB_MAP_9C00, GB_map_type_t_GB_MAP_9800,
+        GB_map_type_t_GB_MAP_AUTO, GB_palette_type_t_GB_PALETTE_AUTO,
+        GB_palette_type_t_GB_PALETTE_BACKGROUND, GB_palette_type_t_GB_PALETTE_NONE,
+        GB_palette_type_t_GB_PALETTE_OAM, GB_tileset_type_t, GB_tileset_type_t_GB_TILESET_8000,
+        GB_tileset_type_t_GB_TILESET_8800, GB_tileset_type_t_GB_TILESET_AUTO, MEMORY_ROW_SIZE,
+        MemoryPage, MemoryRegion, MemoryViewerSettings, ObjectInfo, Objects, PaletteColor,
+        Palettes, Register, VramImages, VramViewerSettings,
+    },
+    show_error,
+};
+
+/// The VRAM viewer's settings, shared between the UI thread (which changes them) and the emulation thread
+/// (which draws the viewer accordingly); `None` while the viewer is hidden.
+pub type SharedVramViewer = Arc<Mutex<Option<VramViewerSettings>>>;
+
+/// Whether the sprites window is shown, shared like [`SharedVramViewer`].
+pub type SharedSpriteViewer = Arc<AtomicBool>;
+
+/// Whether the palettes window is shown, shared like [`SharedVramViewer`].
+pub type SharedPaletteViewer = Arc<AtomicBool>;
+
+/// Whether the debugger window, and thus its CPU state panel, is shown; shared like [`SharedVramViewer`].
+pub type SharedCpuPanel = Arc<AtomicBool>;
+
+/// The memory viewer's settings, shared like [`SharedVramViewer`].
+pub type SharedMemoryViewer = Arc<Mutex<Option<MemoryViewerSettings>>>;
+
+/// The addresses of the breakpoints, as shown in the disassembly pane.
+/// SameBoy doesn't report its breakpoints, so this follows the commands that change them.
+pub type Breakpoints = Arc<Mutex<BTreeSet<u16>>>;
+
+pub struct UiFrontend {
+    main_window: Weak<MainWindow>,
+    debugger_window: Weak<DebuggerWindow>,
+    vram_window: Weak<VramWindow>,
+    vram_viewer: SharedVramViewer,
+    sprites_window: Weak<SpritesWindow>,
+    sprite_viewer: SharedSpriteViewer,
+    palettes_window: Weak<PalettesWindow>,
+    palette_viewer: SharedPaletteViewer,
+    memory_window: Weak<MemoryWindow>,
+    memory_viewer: SharedMemoryViewer,
+    /// What the memory viewer showed last time, to highlight the bytes that changed since.
+    last_memory_page: Option<MemoryPage>,
+    cpu_panel: SharedCpuPanel,
+    breakpoints: Breakpoints,
+}
+
+impl UiFrontend {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        main_window: Weak<MainWindow>,
+        debugger_window: Weak<DebuggerWindow>,
+        vram_window: Weak<VramWindow>,
+        vram_viewer: SharedVramViewer,
+        sprites_window: Weak<SpritesWindow>,
+        sprite_viewer: SharedSpriteViewer,
+        palettes_window: Weak<PalettesWindow>,
+        palette_viewer: SharedPaletteViewer,
+        memory_window: Weak<MemoryWindow>,
+        memory_viewer: SharedMemoryViewer,
+        cpu_panel: SharedCpuPanel,
+        breakpoints: Breakpoints,
+    ) -> Self {
+        Self {
+            main_window,
+            debugger_window,
+            vram_window,
+            vram_viewer,
+            sprites_window,
+            sprite_viewer,
+            palettes_window,
+            palette_viewer,
+            memory_window,
+            memory_viewer,
+            last_memory_page: None,
+            cpu_panel,
+            breakpoints,
+        }
+    }
+}
+
+/// Describes an object's OAM entry, in a fixed-width format so that entries line up.
+fn describe_object(object: &ObjectInfo, cgb_mode: bool) -> String {
+    let flags = object.flags;
+    let mut description = format!(
+        "#{:02} ${:04X}  X {:3}  Y {:3}  Tile ${:02X}  Attr ${flags:02X} ",
+        object.index(),
+        object.oam_addr,
+        object.x,
+        object.y,
+        object.tile,
+    );
+    if cgb_mode {
+        let _ = write!(description, " Pal {} Bank {}", flags & 7, flags >> 3 & 1);
+    } else {
+        let _ = write!(description, " OBP{}", flags >> 4 & 1);
+    }
+    for (bit, name) in [(5, "X flip"), (6, "Y flip"), (7, "Behind BG")] {
+        if flags & 1 << bit != 0 {
+            let _ = write!(description, ", {name}");
+        }
+    }
+    description
+}
+
+/// Formats a palette for the palettes window.
+fn palette_row(label: String, colors: &[PaletteColor; 4]) -> PaletteRow {
+    let swatches: Vec<_> = colors
+        .iter()
+        .map(|color| {
+            let [r, g, b, _] = color.output.to_ne_bytes();
+            let value = match color.value {
+                ColorValue::Rgb555(value) => format!("${value:04X}"),
+                ColorValue::Shade(shade) => format!("Shade {shade}"),
+            };
+            PaletteSwatch {
+                color: slint::Color::from_rgb_u8(r, g, b),
+                value: format!("{value}\n#{r:02X}{g:02X}{b:02X}").into(),
+            }
+        })
+        .collect();
+    PaletteRow {
+        label: label.into(),
+        swatches: ModelRc::new(VecModel::from(swatches)),
+    }
+}
+
+/// Updates the main window's highlight to match the sprite window's hovered entry.
+pub fn highlight_hovered_sprite(main_window: &MainWindow, sprites_window: &SpritesWindow) {
+    let rect = usize::try_from(sprites_window.get_hovered())
+        .ok()
+        .and_then(|index| sprites_window.get_sprites().row_data(index))
+        .map(|row| row.rect)
+        .unwrap_or_default();
+    main_window.set_highlight(rect);
+}
+
+/// Reads the settings selected in the VRAM viewer window.
+pub fn vram_viewer_settings(vram_window: &VramWindow) -> VramViewerSettings {
+    // Same order as the combo boxes.
+    const MAPS: [GB_map_type_t; 3] = [
+        GB_map_type_t_GB_MAP_AUTO,
+        GB_map_type_t_GB_MAP_9800,
+        GB_map_type_t_GB_MAP_9C00,
+    ];
+    const TILESETS: [GB_tileset_type_t; 3] = [
+        GB_tileset_type_t_GB_TILESET_AUTO,
+        GB_tileset_type_t_GB_TILESET_8800,
+        GB_tileset_type_t_GB_TILESET_8000,
+    ];
+
+    let mut settings = VramViewerSettings::default();
+    if let Some(&map) = MAPS.get(vram_window.get_map() as usize) {
+        settings.map = map;
+    }
+    if let Some(&tileset) = TILESETS.get(vram_window.get_tile_data() as usize) {
+        settings.tileset = tileset;
+    }
+    (settings.palette_type, settings.palette_index) = match vram_window.get_palette() {
+        1 => (GB_palette_type_t_GB_PALETTE_NONE, 0),
+        index @ 2..=9 => (GB_palette_type_t_GB_PALETTE_BACKGROUND, index as u8 - 2),
+        index @ 10..=17 => (GB_palette_type_t_GB_PALETTE_OAM, index as u8 - 10),
+        _ => (GB_palette_type_t_GB_PALETTE_AUTO, 0),
+    };
+    settings.bank = (vram_window.get_bank() == 1).into();
+    settings
+}
+
+/// Reads the settings selected in the memory viewer window.
+pub fn memory_viewer_settings(memory_window: &MemoryWindow) -> MemoryViewerSettings {
+    MemoryViewerSettings {
+        region: MemoryRegion::ALL
+            .get(memory_window.get_region() as usize)
+            .copied()
+            .unwrap_or(MemoryRegion::Bus),
+        bank: memory_window.get_bank().clamp(0, u16::MAX.into()) as u16,
+        start: memory_window.get_start().clamp(0, u16::MAX.into()) as u16,
+    }
+}
+
+/// Parses a hexadecimal number, optionally prefixed with `$` or `0x`.
+pub fn parse_hex(text: &str) -> Option<u16> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// Moves the memory viewer to an address, optionally prefixed with a bank (e.g. `01:4000`).
+/// If the current region doesn't contain the address, this switches to the whole bus.
+/// Returns `false` if the address is invalid.
+pub fn memory_go_to(memory_window: &MemoryWindow, text: &str) -> bool {
+    let (bank, addr) = match text.split_once(':') {
+        Some((bank, addr)) => (parse_hex(bank), parse_hex(addr)),
+        None => (Some(memory_window.get_bank() as u16), parse_hex(text)),
+    };
+    let (Some(bank), Some(addr)) = (bank, addr) else {
+        return false;
+    };
+    let settings = memory_viewer_settings(memory_window);
+    if !settings.region.address_range(bank).contains(&addr) {
+        memory_window.set_region(0);
+    }
+    memory_window.set_bank(bank.into());
+    memory_window.set_start(addr.into());
+    true
+}
+
+/// Formats an address for the memory viewer, with its bank if the region has several.
+fn memory_address(page: &MemoryPage, addr: u16) -> String {
+    if page.nb_banks > 1 {
+        format!("{:02X}:{addr:04X}", page.settings.bank)
+    } else {
+        format!("${addr:04X}")
+    }
+}
+
+/// Updates the breakpoint markers in the disassembly pane.
+pub fn mark_breakpoint(debugger_window: &DebuggerWindow, addr: u16, set: bool) {
+    let lines = debugger_window.get_disassembly();
+    for index in 0..lines.row_count() {
+        if let Some(mut line) = lines.row_data(index)
+            && line.address == i32::from(addr)
+        {
+            line.breakpoint = set;
+            lines.set_row_data(index, line);
+        }
+    }
+}
+
+/// Removes all breakpoint markers from the disassembly pane.
+pub fn clear_breakpoints(debugger_window: &DebuggerWindow) {
+    let lines = debugger_window.get_disassembly();
+    for index in 0..lines.row_count() {
+        if let Some(mut line) = lines.row_data(index)
+            && line.breakpoint
+        {
+            line.breakpoint = false;
+            lines.set_row_data(index, line);
+        }
+    }
+}
+
+/// The debugger log's lines. Unlike with a [`VecModel`], the oldest ones can be dropped without shifting all
+/// of the others, nor resetting the view.
+pub struct LogModel {
+    lines: RefCell<VecDeque<LogLine>>,
+    notify: ModelNotify,
+}
+impl LogModel {
+    /// Starts with an empty line, to print to.
+    pub fn new() -> Self {
+        Self {
+            lines: RefCell::new(VecDeque::from([empty_log_line()])),
+            notify: ModelNotify::default(),
+        }
+    }
+
+    fn push(&self, line: LogLine) {
+        let index = {
+            let mut lines = self.lines.borrow_mut();
+            lines.push_back(line);
+            lines.len() - 1
+        };
+        self.notify.row_added(index, 1);
+    }
+
+    /// Drops the `count` oldest lines, and returns them.
+    fn drop_oldest(&self, count: usize) -> Vec<LogLine> {
+        let dropped: Vec<_> = self.lines.borrow_mut().drain(..count).collect();
+        if !dropped.is_empty() {
+            self.notify.row_removed(0, dropped.len());
+        }
+        dropped
+    }
+}
+impl Model for LogModel {
+    type Data = LogLine;
+
+    fn row_count(&self) -> usize {
+        self.lines.borrow().len()
+    }
+
+    fn row_data(&self, row: usize) -> Option<LogLine> {
+        self.lines.borrow().get(row).cloned()
+    }
+
+    fn set_row_data(&self, row: usize, data: LogLine) {
+        if let Some(line) = self.lines.borrow_mut().get_mut(row) {
+            *line = data;
+        } else {
+            return;
+        }
+        self.notify.row_changed(row);
+    }
+
+    fn model_tracker(&self) -> &dyn ModelTracker {
+        &self.notify
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A line of the debugger log with nothing printed on it yet.
+fn empty_log_line() -> LogLine {
+    LogLine {
+        kind: LogKind::Output,
+        spans: ModelRc::new(VecModel::default()),
+        matched: false,
+    }
+}
+
+fn as_vec_model<T: Clone + 'static>(model: &ModelRc<T>) -> &VecModel<T> {
+    model
+        .as_any()
+        .downcast_ref::<VecModel<T>>()
+        .expect("We set this to a VecModel?!?")
+}
+
+fn as_log_model(model: &ModelRc<LogLine>) -> &LogModel {
+    model
+        .as_any()
+        .downcast_ref::<LogModel>()
+        .expect("We set this to a LogModel?!?")
+}
+
+/// Returns the whole text of a line of the debugger log.
+fn log_line_text(line: &LogLine) -> String {
+    line.spans.iter().fold(String::new(), |mut text, span| {
+        text.push_str(&span.text);
+        text
+    })
+}
+
+/// Returns the debugger log as plain text.
+pub fn log_text(debugger_window: &DebuggerWindow) -> String {
+    debugger_window
+        .get_log()
+        .iter()
+        .fold(String::new(), |mut text, line| {
+            text.push_str(&log_line_text(&line));
+            text.push('\n');
+            text
+        })
+}
+
+/// Appends text printed by SameBoy to the debugger log, starting a new line after each line break.
+fn append_log(debugger_window: &DebuggerWindow, text: &str, attributes: GB_log_attributes_t) {
+    let log = debugger_window.get_log();
+    let log = as_log_model(&log);
+    let search = debugger_window.get_search().to_lowercase();
+
+    let mut lines = text.split('\n');
+    // `split` always yields at least one item.
+    append_to_last_line(
+        debugger_window,
+        log,
+        &search,
+        lines.next().unwrap(),
+        attributes,
+    );
+    for line in lines {
+        log.push(empty_log_line());
+        append_to_last_line(debugger_window, log, &search, line, attributes);
+    }
+    trim_log(debugger_window);
+}
+
+fn append_to_last_line(
+    debugger_window: &DebuggerWindow,
+    log: &LogModel,
+    search: &str,
+    text: &str,
+    attributes: GB_log_attributes_t,
+) {
+    if text.is_empty() {
+        return;
+    }
+    let index = log.row_count() - 1;
+    let mut line = log.row_data(index).unwrap();
+    let spans = as_vec_model(&line.spans);
+
+    let underline = attributes & GB_log_attributes_t_GB_LOG_UNDERLINE_MASK;
+    let style = LogSpan {
+        text: SharedString::new(),
+        bold: attributes & GB_log_attributes_t_GB_LOG_BOLD != 0,
+        underline: underline == GB_log_attributes_t_GB_LOG_UNDERLINE,
+        dashed_underline: underline == GB_log_attributes_t_GB_LOG_DASHED_UNDERLINE,
+    };
+    // Text often arrives in small pieces; extend the last span if it's styled the same.
+    let last = spans.row_count().checked_sub(1);
+    match last.and_then(|last| spans.row_data(last)) {
+        Some(mut span)
+            if span.bold == style.bold
+                && span.underline == style.underline
+                && span.dashed_underline == style.dashed_underline =>
+        {
+            span.text.push_str(text);
+            spans.set_row_data(last.unwrap(), span);
+        }
+        _ => spans.push(LogSpan {
+            text: text.into(),
+            ..style
+        }),
+    }
+
+    let whole = log_line_text(&line);
+    let kind = match line.kind {
+        LogKind::Command => LogKind::Command,
+        _ => log_kind(&whole),
+    };
+    let matched = log_line_matches(&whole, search);
+    if kind != line.kind || matched != line.matched {
+        if matched != line.matched {
+            let delta = if matched { 1 } else { -1 };
+            debugger_window.set_search_matches(debugger_window.get_search_matches() + delta);
+        }
+        line.kind = kind;
+        line.matched = matched;
+        log.set_row_data(index, line);
+    }
+}
+
+/// SameBoy doesn't tell errors apart from other output, so this guesses from the wording.
+fn log_kind(line: &str) -> LogKind {
+    const ERROR_PREFIXES: [&str; 6] = ["error", "invalid", "unknown", "unable", "cannot", "can't"];
+
+    let line = line.trim_start().to_ascii_lowercase();
+    if line.starts_with("warning") {
+        LogKind::Warning
+    } else if ERROR_PREFIXES.iter().any(|prefix| line.starts_with(prefix))
+        || line.contains(" error")
+    {
+        LogKind::Error
+    } else {
+        LogKind::Output
+    }
+}
+
+/// `search` must already be lowercase; searching is case-insensitive.
+fn log_line_matches(line: &str, search: &str) -> bool {
+    !search.is_empty() && line.to_lowercase().contains(search)
+}
+
+/// Echoes a command typed in by the user to the debugger log, then starts a new line for its output.
+pub fn push_log_command(debugger_window: &DebuggerWindow, command: &str) {
+    let log = debugger_window.get_log();
+    let log = as_log_model(&log);
+
+    let matched = log_line_matches(command, &debugger_window.get_search().to_lowercase());
+    if matched {
+        debugger_window.set_search_matches(debugger_window.get_search_matches() + 1);
+    }
+    let line = LogLine {
+        kind: LogKind::Command,
+        spans: ModelRc::new(VecModel::from(vec![LogSpan {
+            text: command.into(),
+            ..Default::default()
+        }])),
+        matched,
+    };
+    // Reuse the last line if nothing has been printed on it.
+    let last = log.row_count() - 1;
+    if log
+        .row_data(last)
+        .is_some_and(|line| line.spans.row_count() == 0)
+    {
+        log.set_row_data(last, line);
+    } else {
+        log.push(line);
+    }
+    log.push(empty_log_line());
+    trim_log(debugger_window);
+}
+
+/// Drops the oldest lines of the debugger log beyond its configured length.
+pub fn trim_log(debugger_window: &DebuggerWindow) {
+    let log = debugger_window.get_log();
+    let log = as_log_model(&log);
+    // Always keep the line being printed to.
+    let max_lines = usize::try_from(debugger_window.get_log_length()).map_or(1, |len| len.max(1));
+
+    let excess = log.row_count().saturating_sub(max_lines);
+    let dropped_matches = log
+        .drop_oldest(excess)
+        .iter()
+        .filter(|line| line.matched)
+        .count();
+    if dropped_matches != 0 {
+        debugger_window
+            .set_search_matches(debugger_window.get_search_matches() - dropped_matches as i32);
+    }
+}
+
+/// Highlights the lines of the debugger log that contain the search text.
+pub fn search_log(debugger_window: &DebuggerWindow) {
+    let log = debugger_window.get_log();
+    let search = debugger_window.get_search().to_lowercase();
+
+    let mut matches = 0;
+    for index in 0..log.row_count() {
+        let mut line = log.row_data(index).unwrap();
+        let matched = log_line_matches(&log_line_text(&line), &search);
+        matches += i32::from(matched);
+        // Avoid redrawing lines that don't change.
+        if matched != line.matched {
+            line.matched = matched;
+            log.set_row_data(index, line);
+        }
+    }
+    debugger_window.set_search_matches(matches);
+}
+
+impl Frontend for UiFrontend {
+    fn update_screen(&mut self, screen: SharedPixelBuffer<Rgba8Pixel>) {
+        let main_window = self.main_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            if let Some(main_window) = main_window.upgrade() {
+                main_window.set_screen(slint::Image::from_rgba8(screen));
+            }
+        });
+    }
+
+    fn log(&mut self, text: &str, attributes: GB_log_attributes_t) {
+        let text = text.to_owned();
+        let debugger_window = self.debugger_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            // Do nothing if the debugger window has been destroyed.
+            if let Some(debugger_window) = debugger_window.upgrade() {
+                append_log(&debugger_window, &text, attributes);
+            }
+        });
+    }
+
+    fn recording_aborted(&mut self, err: io::Error) {
+        let main_window = self.main_window.clone();
+        // It's fine if we fail to report this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            if let Some(main_window) = main_window.upgrade() {
+                main_window.set_recording(false);
+            }
+            show_error("Recording stopped", &err);
+        });
+    }
+
+    fn vram_viewer(&self) -> Option<VramViewerSettings> {
+        *self.vram_viewer.lock().unwrap()
+    }
+
+    fn update_vram_viewer(&mut self, images: VramImages) {
+        let vram_window = self.vram_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            if let Some(vram_window) = vram_window.upgrade() {
+                vram_window.set_tileset(slint::Image::from_rgba8(images.tileset));
+                vram_window.set_tilemap(slint::Image::from_rgba8(images.tilemap));
+            }
+        });
+    }
+
+    fn sprite_viewer_shown(&self) -> bool {
+        self.sprite_viewer.load(Ordering::Relaxed)
+    }
+
+    fn update_sprite_viewer(&mut self, objects: Objects) {
+        let (origin_x, origin_y) = objects.screen_origin;
+        let rows: Vec<_> = objects
+            .objects
+            .into_iter()
+            .map(|object| {
+                let status = if !object.on_screen(objects.height) {
+                    "Off screen"
+                } else if object.dropped {
+                    "Dropped (10 per line)"
+                } else {
+                    "On screen"
+                };
+                let rect = ScreenRect {
+                    x: origin_x as i32 + i32::from(object.x) - 8,
+                    y: origin_y as i32 + i32::from(object.y) - 16,
+                    width: 8,
+                    height: objects.height.into(),
+                };
+                let description = describe_object(&object, objects.cgb_mode);
+                (object.image, description, status, rect)
+            })
+            .collect();
+
+        let main_window = self.main_window.clone();
+        let sprites_window = self.sprites_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            let Some(sprites_window) = sprites_window.upgrade() else {
+                return;
+            };
+            let rows: Vec<_> = rows
+                .into_iter()
+                .map(|(image, description, status, rect)| SpriteRow {
+                    image: slint::Image::from_rgba8(image),
+                    description: description.into(),
+                    status: status.into(),
+                    rect,
+                })
+                .collect();
+            sprites_window.set_sprites(ModelRc::new(VecModel::from(rows)));
+            // The hovered object may have moved.
+            if let Some(main_window) = main_window.upgrade() {
+                highlight_hovered_sprite(&main_window, &sprites_window);
+            }
+        });
+    }
+
+    fn palette_viewer_shown(&self) -> bool {
+        self.palette_viewer.load(Ordering::Relaxed)
+    }
+
+    fn update_palette_viewer(&mut self, palettes: Palettes) {
+        let palettes_window = self.palettes_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            let Some(palettes_window) = palettes_window.upgrade() else {
+                return;
+            };
+            let dmg = !palettes.cgb;
+            let background: Vec<_> = palettes
+                .background
+                .iter()
+                .enumerate()
+                .map(|(index, colors)| {
+                    let label = if dmg {
+                        "BGP".into()
+                    } else {
+                        format!("{index}")
+                    };
+                    palette_row(label, colors)
+                })
+                .collect();
+            let objects: Vec<_> = palettes
+                .objects
+                .iter()
+                .enumerate()
+                .map(|(index, colors)| {
+                    let label = if dmg {
+                        format!("OBP{index}")
+                    } else {
+                        format!("{index}")
+                    };
+                    palette_row(label, colors)
+                })
+                .collect();
+            palettes_window.set_background_palettes(ModelRc::new(VecModel::from(background)));
+            palettes_window.set_object_palettes(ModelRc::new(VecModel::from(objects)));
+        });
+    }
+
+    fn memory_viewer(&self) -> Option<MemoryViewerSettings> {
+        *self.memory_viewer.lock().unwrap()
+    }
+
+    fn update_memory_viewer(&mut self, page: MemoryPage) {
+        let previous = self
+            .last_memory_page
+            .take()
+            .filter(|previous| previous.settings == page.settings)
+            .map(|previous| previous.bytes)
+            .unwrap_or_default();
+        let mut symbols = page.symbols.iter().peekable();
+        let rows: Vec<_> = page
+            .bytes
+            .chunks(MEMORY_ROW_SIZE)
+            .enumerate()
+            .map(|(row, bytes)| {
+                let offset = row * MEMORY_ROW_SIZE;
+                let addr = page.settings.start + offset as u16;
+                let cells: Vec<_> = bytes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, byte)| {
+                        let changed = previous
+                            .get(offset + i)
+                            .is_some_and(|previous| previous != byte);
+                        (
+                            memory_address(&page, addr + i as u16),
+                            format!("{byte:02X}"),
+                            changed,
+                        )
+                    })
+                    .collect();
+                let ascii: String = bytes
+                    .iter()
+                    .map(|&byte| match byte {
+                        0x20..=0x7E => char::from(byte),
+                        _ => '.',
+                    })
+                    .collect();
+                let mut names = vec![];
+                while let Some((_, name)) =
+                    symbols.next_if(|(symbol_offset, _)| *symbol_offset < offset + MEMORY_ROW_SIZE)
+                {
+                    names.push(name.as_str());
+                }
+                (
+                    addr,
+                    memory_address(&page, addr),
+                    cells,
+                    ascii,
+                    names.join(", "),
+                )
+            })
+            .collect();
+
+        let settings = page.settings;
+        let nb_banks = page.nb_banks;
+        self.last_memory_page = Some(page);
+        let memory_window = self.memory_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            let Some(memory_window) = memory_window.upgrade() else {
+                return;
+            };
+            let rows: Vec<_> = rows
+                .into_iter()
+                .map(|(addr, label, cells, ascii, symbols)| {
+                    let cells: Vec<_> = cells
+                        .into_iter()
+                        .map(|(address, value, changed)| MemoryCell {
+                            address: address.into(),
+                            value: value.into(),
+                            changed,
+                        })
+                        .collect();
+                    MemoryRow {
+                        address: addr.into(),
+                        label: label.into(),
+                        cells: ModelRc::new(VecModel::from(cells)),
+                        ascii: ascii.into(),
+                        symbols: symbols.into(),
+                    }
+                })
+                .collect();
+            memory_window.set_rows(ModelRc::new(VecModel::from(rows)));
+            memory_window.set_bank_count(nb_banks.into());
+            // Reflect where the page actually ended up, so that scrolling starts from there.
+            memory_window.set_bank(settings.bank.into());
+            memory_window.set_start(settings.start.into());
+        });
+    }
+
+    fn cpu_panel_shown(&self) -> bool {
+        self.cpu_panel.load(Ordering::Relaxed)
+    }
+
+    fn update_cpu_panel(&mut self, state: CpuState) {
+        let registers: Vec<_> = Register::ALL
+            .into_iter()
+            .map(|register| {
+                let name = register.name().to_uppercase();
+                (name, format!("${:04X}", state.registers.get(register)))
+            })
+            .collect();
+        let flags: String = [(7, 'Z'), (6, 'N'), (5, 'H'), (4, 'C')]
+            .into_iter()
+            .map(|(bit, name)| {
+                if state.registers.af & 1 << bit != 0 {
+                    name
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let sram_bank = match state.sram_bank {
+            Some(bank) => format!("${bank:02X}"),
+            None => "none".into(),
+        };
+        let banks = format!(
+            "ROM bank ${:02X}\nSRAM bank {sram_bank}\nWRAM bank {}\nVRAM bank {}",
+            state.rom_bank, state.wram_bank, state.vram_bank,
+        );
+
+        let debugger_window = self.debugger_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            let Some(debugger_window) = debugger_window.upgrade() else {
+                return;
+            };
+            let registers: Vec<_> = registers
+                .into_iter()
+                .map(|(name, value)| RegisterRow {
+                    name: name.into(),
+                    value: value.into(),
+                })
+                .collect();
+            debugger_window.set_registers(ModelRc::new(VecModel::from(registers)));
+            debugger_window.set_flags(flags.into());
+            debugger_window.set_ime(state.ime);
+            debugger_window.set_halted(state.halted);
+            debugger_window.set_banks(banks.into());
+        });
+    }
+
+    fn debugger_stopped(&mut self, stopped: bool) {
+        let main_window = self.main_window.clone();
+        let debugger_window = self.debugger_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            if let Some(main_window) = main_window.upgrade() {
+                main_window.set_debugger_stopped(stopped);
+            }
+            if let Some(debugger_window) = debugger_window.upgrade() {
+                debugger_window.set_stopped(stopped);
+            }
+        });
+    }
+
+    fn update_disassembly(&mut self, lines: Vec<DisassemblyLine>) {
+        let breakpoints = self.breakpoints.lock().unwrap().clone();
+        let debugger_window = self.debugger_window.clone();
+        // It's fine if we fail to update this due to the main loop being closed; we'll shut down soon anyway.
+        let _ = slint::invoke_from_event_loop(move || {
+            let Some(debugger_window) = debugger_window.upgrade() else {
+                return;
+            };
+            let rows: Vec<_> = lines
+                .into_iter()
+                .map(|line| DisassemblyRow {
+                    address: line.addr.map_or(-1, i32::from),
+                    text: line.text.into(),
+                    current: line.current,
+                    breakpoint: line.addr.is_some_and(|addr| breakpoints.contains(&addr)),
+                })
+                .collect();
+            debugger_window.set_disassembly(ModelRc::new(VecModel::from(rows)));
+        });
+    }
+}